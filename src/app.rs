@@ -1,13 +1,20 @@
 use axum::http::header;
 use axum::Router;
 use tower_http::{
-    compression::CompressionLayer, cors::CorsLayer, propagate_header::PropagateHeaderLayer,
-    sensitive_headers::SetSensitiveHeadersLayer, trace,
+    compression::CompressionLayer,
+    cors::CorsLayer,
+    propagate_header::PropagateHeaderLayer,
+    request_id::{MakeRequestUuid, SetRequestIdLayer},
+    sensitive_headers::SetSensitiveHeadersLayer,
+    trace,
 };
 
 use crate::logger;
 use crate::models;
 use crate::routes;
+use crate::utils::emotion_wheel;
+use crate::utils::password_policy;
+use crate::utils::signing_keys;
 
 pub async fn create_app() -> Router {
     logger::setup();
@@ -16,12 +23,26 @@ pub async fn create_app() -> Router {
         .await
         .expect("Failed to sync database indexes");
 
+    // Load the signing keys, the emotion wheel and the banned password list
+    // now so a broken file stops the server here
+    signing_keys::load();
+    emotion_wheel::wheel();
+    password_policy::load();
+
     Router::new()
         .merge(routes::status::create_route())
+        .merge(routes::well_known::create_route())
         .merge(routes::user::create_route())
-        .merge(routes::auth::create_route())  // Add the auth routes
+        .merge(routes::auth::create_route()) // Add the auth routes
+        .merge(routes::mfa::create_route())
+        .merge(routes::api_token::create_route())
+        .merge(routes::session::create_route())
+        .merge(routes::data_export::create_route())
         .merge(routes::checkin::create_route())
+        .merge(routes::emotion::create_route())
+        .merge(routes::tag::create_route())
         .merge(routes::meditation::create_route())
+        .merge(routes::admin::create_route())
         .merge(Router::new().nest(
             "/v1",
             // All public v1 routes will be nested here.
@@ -45,7 +66,10 @@ pub async fn create_app() -> Router {
         .layer(PropagateHeaderLayer::new(header::HeaderName::from_static(
             "x-request-id",
         )))
+        // Give an `X-Request-Id` to requests without one, so they can be
+        // traced back from the audit log
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         // CORS configuration. This should probably be more restrictive in
         // production.
         .layer(CorsLayer::permissive())
-}
\ No newline at end of file
+}
@@ -1,11 +1,13 @@
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use bcrypt::BcryptError;
 use serde_json::json;
 use tokio::task::JoinError;
+use validator::{ValidationError, ValidationErrors};
 use wither::bson;
 use wither::mongodb::error::Error as MongoError;
+use wither::mongodb::error::{ErrorKind as MongoErrorKind, WriteFailure};
 use wither::WitherError;
 
 #[derive(thiserror::Error, Debug)]
@@ -29,20 +31,44 @@ pub enum Error {
     #[error("{0}")]
     BadRequest(#[from] BadRequest),
 
+    #[error("Validation error")]
+    Validation(#[from] ValidationErrors),
+
     #[error("{0}")]
     NotFound(#[from] NotFound),
 
+    #[error("{0}")]
+    Unauthorized(#[from] Unauthorized),
+
+    #[error("{0}")]
+    Forbidden(#[from] Forbidden),
+
+    #[error("{0}")]
+    TooManyRequests(#[from] TooManyRequests),
+
     #[error("{0}")]
     RunSyncTask(#[from] JoinError),
 
     #[error("{0}")]
     HashPassword(#[from] BcryptError),
-    
+
+    #[error("{0}")]
+    PasswordHash(#[from] argon2::password_hash::Error),
+
     #[error("{0}")]
     TokenCreation(String),
 
     #[error("Error invalid password {0}")]
     InvalidPassword(String),
+
+    #[error("Error sending email {0}")]
+    SendMail(String),
+
+    #[error("Identity provider error {0}")]
+    IdentityProvider(String),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl Error {
@@ -55,6 +81,7 @@ impl Error {
             Error::Authenticate(AuthenticateError::WrongCredentials) => {
                 (StatusCode::UNAUTHORIZED, 40004)
             }
+            Error::Unauthorized(_) => (StatusCode::UNAUTHORIZED, 40004),
             Error::Authenticate(AuthenticateError::InvalidToken) => {
                 (StatusCode::UNAUTHORIZED, 40005)
             }
@@ -62,15 +89,19 @@ impl Error {
             Error::TokenCreation(_) => (StatusCode::INTERNAL_SERVER_ERROR, 40007),
 
             // 5XX Errors
-            Error::Authenticate(AuthenticateError::TokenCreation) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, 5001)
-            }
             Error::Wither(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5002),
             Error::Mongo(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
             Error::SerializeMongoResponse(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5004),
             Error::RunSyncTask(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5005),
             Error::HashPassword(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5006),
+            Error::PasswordHash(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5006),
+            Error::SendMail(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5007),
+            Error::IdentityProvider(_) => (StatusCode::BAD_GATEWAY, 5008),
+            Error::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
             Error::InvalidPassword(_) => (StatusCode::UNAUTHORIZED, 40008),
+            Error::Forbidden(_) => (StatusCode::FORBIDDEN, 40009),
+            Error::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, 40010),
+            Error::Validation(_) => (StatusCode::BAD_REQUEST, 40011),
         }
     }
 
@@ -79,7 +110,7 @@ impl Error {
             message: "Bad Request".to_string(),
         })
     }
-    
+
     pub fn bad_request_with_message(message: String) -> Self {
         Error::BadRequest(BadRequest { message })
     }
@@ -88,22 +119,60 @@ impl Error {
         Error::NotFound(NotFound {})
     }
 
+    pub fn forbidden_with_message(message: String) -> Self {
+        Error::Forbidden(Forbidden { message })
+    }
+
+    /// Whether a write was refused by a unique index.
+    pub fn is_duplicate_key(&self) -> bool {
+        const DUPLICATE_KEY: i32 = 11000;
+
+        let err = match self {
+            Error::Mongo(err) | Error::Wither(WitherError::Mongo(err)) => err,
+            _ => return false,
+        };
+
+        match err.kind.as_ref() {
+            MongoErrorKind::Write(WriteFailure::WriteError(err)) => err.code == DUPLICATE_KEY,
+            MongoErrorKind::Command(err) => err.code == DUPLICATE_KEY,
+            _ => false,
+        }
+    }
+
     pub fn unauthorized_with_message(message: String) -> Self {
-        Error::Authenticate(AuthenticateError::WrongCredentials)
+        Error::Unauthorized(Unauthorized { message })
     }
 }
 
+/// An error of a single field, to be reported with `Error::Validation`.
+pub fn invalid_field(message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+    error
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let (status_code, code) = self.get_codes();
         let message = self.to_string();
-        
+
         // Create error response format in line with the app's expected format
-        let body = Json(json!({
+        let mut body = json!({
             "success": false,
             "message": message,
             "error": code.to_string()
-        }));
+        });
+
+        // Validation errors are detailed per field
+        if let Error::Validation(errors) = &self {
+            body["errors"] = json!(errors);
+        }
+        let body = Json(body);
+
+        if let Error::TooManyRequests(TooManyRequests { retry_after }) = self {
+            let headers = [(header::RETRY_AFTER, retry_after.to_string())];
+            return (status_code, headers, body).into_response();
+        }
 
         (status_code, body).into_response()
     }
@@ -114,8 +183,6 @@ impl IntoResponse for Error {
 pub enum AuthenticateError {
     #[error("Wrong authentication credentials")]
     WrongCredentials,
-    #[error("Failed to create authentication token")]
-    TokenCreation,
     #[error("Invalid authentication credentials")]
     InvalidToken,
     #[error("User is locked")]
@@ -130,4 +197,23 @@ pub struct BadRequest {
 
 #[derive(thiserror::Error, Debug)]
 #[error("Not found")]
-pub struct NotFound {}
\ No newline at end of file
+pub struct NotFound {}
+
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct Unauthorized {
+    pub message: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct Forbidden {
+    pub message: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Too many requests, retry in {retry_after} seconds")]
+pub struct TooManyRequests {
+    /// Seconds to wait before retrying.
+    pub retry_after: i64,
+}
@@ -0,0 +1,153 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bson::doc;
+use tracing::{error, info};
+
+use crate::errors::Error;
+use crate::models::account_deletion::{AccountDeletion, ErasedCounts};
+use crate::models::api_token::ApiToken;
+use crate::models::audit_event;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::cat::Cat;
+use crate::models::checkin::Checkin;
+use crate::models::checkin_summary::CheckinSummary;
+use crate::models::data_export;
+use crate::models::data_export::DataExport;
+use crate::models::meditation_track::{MeditationTrack, MUSIC_DIR};
+use crate::models::one_time_token::OneTimeToken;
+use crate::models::refresh_token::RefreshToken;
+use crate::models::session::Session;
+use crate::models::signin_throttle;
+use crate::models::tag::Tag;
+use crate::models::user::User;
+use crate::services::audit;
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::request_context::RequestContext;
+
+/// Periodically erases the accounts whose deletion grace period is over.
+pub async fn run() {
+    let period = Duration::from_secs(SETTINGS.account_deletion.job_interval);
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = erase_due_accounts().await {
+            error!("Failed to erase accounts scheduled for deletion: {}", err);
+        }
+    }
+}
+
+async fn erase_due_accounts() -> Result<(), Error> {
+    let users = User::find(
+        doc! { "deletion_scheduled_at": { "$lte": date::now() } },
+        None,
+    )
+    .await?;
+
+    // An account that can't be erased is retried on the next run, it doesn't
+    // hold up the others
+    for user in users {
+        if let Err(err) = erase(user).await {
+            error!("Failed to erase an account scheduled for deletion: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+// The account is claimed before any data is erased, which keeps the deletion
+// from being cancelled halfway through. Erasing an account twice is harmless,
+// so several instances of the job can run at once. Only the one removing the
+// user writes the audit record. Every model holding user data has to be
+// erased here.
+async fn erase(user: User) -> Result<(), Error> {
+    let user_id = user.id.unwrap();
+    let claimed = User::find_one_and_update(
+        doc! { "_id": &user_id, "deletion_scheduled_at": { "$lte": date::now() } },
+        doc! { "$set": { "erasing_at": date::now() } },
+    )
+    .await?;
+
+    // The deletion was cancelled or the account is already erased
+    let user = match claimed {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let scheduled_at = user.deletion_scheduled_at.unwrap();
+
+    let mut erased = ErasedCounts {
+        meditation_tracks: erase_meditation_tracks(&user).await?,
+        data_exports: erase_data_exports(&user).await?,
+        ..Default::default()
+    };
+
+    let query = doc! { "user": &user_id };
+    erased.checkins = Checkin::delete_many(query.clone()).await?.deleted_count;
+    CheckinSummary::delete_many(query.clone()).await?;
+    erased.tags = Tag::delete_many(query.clone()).await?.deleted_count;
+    erased.cats = Cat::delete_many(query.clone()).await?.deleted_count;
+    erased.api_tokens = ApiToken::delete_many(query.clone()).await?.deleted_count;
+    erased.sessions = Session::delete_many(query.clone()).await?.deleted_count;
+    erased.refresh_tokens = RefreshToken::delete_many(query.clone())
+        .await?
+        .deleted_count;
+    erased.one_time_tokens = OneTimeToken::delete_many(query).await?.deleted_count;
+    signin_throttle::clear(&user.email).await?;
+    audit_event::pseudonymise(&user_id, &user.email).await?;
+
+    let result = User::delete_one(doc! { "_id": &user_id, "erasing_at": { "$ne": null } }).await?;
+
+    if result.deleted_count == 1 {
+        let record = AccountDeletion::new(user.created_at, scheduled_at, erased);
+        let record = AccountDeletion::create(record).await?;
+        info!("Erased an account scheduled for deletion");
+
+        // Erasures aren't triggered by a request, and the event must not
+        // tie the erased id to anything
+        let event = AuditEvent::new(AuditEventKind::AccountErased)
+            .details(doc! { "account_deletion": record.id });
+        audit::emit(&RequestContext::default(), event).await;
+    }
+
+    Ok(())
+}
+
+async fn erase_data_exports(user: &User) -> Result<u64, Error> {
+    let exports = DataExport::find(doc! { "user": &user.id }, None).await?;
+
+    for export in &exports {
+        data_export::remove(export).await?;
+    }
+
+    Ok(exports.len() as u64)
+}
+
+async fn erase_meditation_tracks(user: &User) -> Result<u64, Error> {
+    let tracks = MeditationTrack::find(doc! { "user": &user.id }, None).await?;
+
+    for track in &tracks {
+        // Filenames are generated by the server, but are still kept from
+        // pointing outside of the music directory
+        let filename = PathBuf::from(&track.filename);
+        let filename = match filename.file_name() {
+            Some(filename) => filename,
+            None => continue,
+        };
+
+        let path = PathBuf::from(MUSIC_DIR).join(filename);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::Io(err)),
+        }
+    }
+
+    MeditationTrack::delete_many(doc! { "user": &user.id }).await?;
+
+    Ok(tracks.len() as u64)
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bson::{doc, oid::ObjectId};
+use futures::stream::TryStreamExt;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use wither::mongodb::options::FindOptions;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::errors::Error;
+use crate::mailer;
+use crate::mailer::Email;
+use crate::models::checkin::{Checkin, PublicCheckin};
+use crate::models::data_export;
+use crate::models::data_export::DataExport;
+use crate::models::meditation_track::{
+    MeditationTrack, PublicMeditationSession, PublicMeditationTrack, MUSIC_DIR,
+};
+use crate::models::tag::{PublicTag, Tag};
+use crate::models::user::{PublicUser, User};
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+
+/// How many entries can wait for the archive writer.
+const ENTRY_BUFFER: usize = 64;
+
+/// Builds the archive of an export in the background.
+pub fn spawn(export: ObjectId) {
+    tokio::spawn(async move {
+        if let Err(err) = build(&export).await {
+            error!("Failed to build data export {}: {}", export, err);
+
+            let update = doc! { "$set": { "status": "failed", "updated_at": date::now() } };
+            if let Err(err) = DataExport::update_one(doc! { "_id": export }, update, None).await {
+                error!("Failed to mark data export {} as failed: {}", export, err);
+            }
+        }
+    });
+}
+
+/// Periodically removes the exports whose download link expired and builds
+/// again the ones that were abandoned. The first run happens on startup.
+pub async fn run() {
+    let period = Duration::from_secs(SETTINGS.data_export.cleanup_interval);
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = resume_abandoned_exports().await {
+            error!("Failed to resume abandoned data exports: {}", err);
+        }
+        if let Err(err) = remove_expired_exports().await {
+            error!("Failed to remove expired data exports: {}", err);
+        }
+    }
+}
+
+// Builds only live in memory, so an export still pending long after it was
+// requested was lost, usually by a restart. Each export is claimed by bumping
+// its `updated_at` before being built again, so when several instances run
+// only one of them picks it up.
+async fn resume_abandoned_exports() -> Result<(), Error> {
+    loop {
+        let timeout = chrono::Duration::seconds(SETTINGS.data_export.build_timeout);
+        let now = date::now();
+        let abandoned_before: bson::DateTime = (now.to_chrono() - timeout).into();
+
+        let export = DataExport::find_one_and_update(
+            doc! { "status": "pending", "updated_at": { "$lte": abandoned_before } },
+            doc! { "$set": { "updated_at": now } },
+        )
+        .await?;
+
+        let export = match export {
+            Some(export) => export,
+            None => return Ok(()),
+        };
+
+        warn!(
+            "Data export {} was abandoned, building it again",
+            export.id.unwrap()
+        );
+        spawn(export.id.unwrap());
+    }
+}
+
+async fn remove_expired_exports() -> Result<(), Error> {
+    let exports = DataExport::find(doc! { "expires_at": { "$lte": date::now() } }, None).await?;
+
+    for export in exports {
+        data_export::remove(&export).await?;
+    }
+
+    Ok(())
+}
+
+async fn build(export: &ObjectId) -> Result<(), Error> {
+    let export = DataExport::find_by_id(export)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    let user = User::find_by_id(&export.user)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    // The archive is written next to its final path, so a partially written
+    // archive is never downloaded. Each build has its own, an abandoned build
+    // may still be running when the export is built again
+    let path = export.path();
+    let partial_path = path.with_extension(format!("zip.{}.part", ObjectId::new()));
+    tokio::fs::create_dir_all(&SETTINGS.data_export.dir).await?;
+    write_archive(&user, &partial_path).await?;
+    tokio::fs::rename(&partial_path, &path).await?;
+
+    let token = opaque_token::generate();
+    let now = date::now();
+    let expires_at = data_export::download_expires_at(now);
+    DataExport::update_one(
+        doc! { "_id": export.id },
+        doc! { "$set": {
+            "status": "ready",
+            "download_token_hash": opaque_token::hash(&token),
+            "expires_at": expires_at,
+            "updated_at": now
+        } },
+        None,
+    )
+    .await?;
+    info!("Data export {} is ready", export.id.unwrap());
+
+    let link = mailer::link("/download-export", &token);
+    let email = Email::new(
+        user.email.clone(),
+        "Your data export is ready",
+        format!(
+            "Hi {},\n\nThe export of your data is ready, you can download it using the \
+            following link:\n\n{}\n\nThe link expires in {} hours.",
+            user.first_name,
+            link,
+            SETTINGS.data_export.download_ttl / 3600
+        ),
+    );
+
+    mailer::mailer().send(email).await
+}
+
+// The archive is written by a blocking task, zipping and copying the track
+// files would otherwise hold up a runtime thread. Documents are streamed to it
+// from the database so histories are never loaded whole.
+async fn write_archive(user: &User, path: &Path) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel(ENTRY_BUFFER);
+    let path = path.to_path_buf();
+    let writer = tokio::task::spawn_blocking(move || write_entries(&path, receiver));
+
+    let sent = send_entries(user, ArchiveSender(sender)).await;
+
+    // When the writer fails sending fails too, its error is the meaningful one
+    writer.await??;
+    sent
+}
+
+enum Entry {
+    /// Starts a new file in the archive, the data that follows goes to it.
+    File(String),
+    Data(Vec<u8>),
+    /// Copies a meditation track into the archive.
+    Track(String),
+}
+
+struct ArchiveSender(mpsc::Sender<Entry>);
+
+impl ArchiveSender {
+    async fn send(&self, entry: Entry) -> Result<(), Error> {
+        self.0.send(entry).await.map_err(|_| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "The archive writer stopped",
+            ))
+        })
+    }
+
+    async fn start_file(&self, name: &str) -> Result<(), Error> {
+        self.send(Entry::File(name.to_string())).await
+    }
+
+    async fn write(&self, data: impl Into<Vec<u8>>) -> Result<(), Error> {
+        self.send(Entry::Data(data.into())).await
+    }
+
+    async fn write_json<T: Serialize>(&self, value: &T) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(value).map_err(io::Error::from)?;
+        self.write(data).await
+    }
+}
+
+fn write_entries(path: &Path, mut entries: mpsc::Receiver<Entry>) -> Result<(), Error> {
+    let file = File::create(path)?;
+    let mut archive = ZipWriter::new(BufWriter::new(file));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    while let Some(entry) = entries.blocking_recv() {
+        match entry {
+            Entry::File(name) => archive.start_file(name, options).map_err(io::Error::from)?,
+            Entry::Data(data) => archive.write_all(&data)?,
+            Entry::Track(filename) => write_meditation_track(&filename, &mut archive, options)?,
+        }
+    }
+
+    archive.finish().map_err(io::Error::from)?.flush()?;
+
+    Ok(())
+}
+
+async fn send_entries(user: &User, archive: ArchiveSender) -> Result<(), Error> {
+    archive.start_file("profile.json").await?;
+    archive.write_json(&PublicUser::from(user.clone())).await?;
+
+    archive.start_file("checkins.json").await?;
+    send_checkins_json(user, &archive).await?;
+
+    let tags = Tag::find(doc! { "user": &user.id }, None).await?;
+    let tag_names = tags
+        .iter()
+        .map(|tag| (tag.id.unwrap(), tag.name.clone()))
+        .collect::<HashMap<ObjectId, String>>();
+
+    archive.start_file("checkins.csv").await?;
+    send_checkins_csv(user, &tag_names, &archive).await?;
+
+    let tags = tags.into_iter().map(Into::into).collect::<Vec<PublicTag>>();
+    archive.start_file("tags.json").await?;
+    archive.write_json(&tags).await?;
+
+    let tracks = MeditationTrack::find(doc! { "user": &user.id }, None).await?;
+
+    // Tracks generated before sessions were recorded have none
+    let sessions = tracks
+        .iter()
+        .filter_map(PublicMeditationSession::new)
+        .collect::<Vec<PublicMeditationSession>>();
+    archive.start_file("meditation_sessions.json").await?;
+    archive.write_json(&sessions).await?;
+
+    for track in &tracks {
+        archive.send(Entry::Track(track.filename.clone())).await?;
+    }
+    let tracks = tracks
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<PublicMeditationTrack>>();
+    archive.start_file("meditation_tracks.json").await?;
+    archive.write_json(&tracks).await?;
+
+    Ok(())
+}
+
+// Check-ins are streamed from the database, histories can be long
+async fn send_checkins_json(user: &User, archive: &ArchiveSender) -> Result<(), Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": 1_i32 })
+        .build();
+    let mut cursor = Checkin::cursor(doc! { "user": &user.id }, options).await?;
+
+    let mut separator = "\n  ";
+    archive.write("[").await?;
+    while let Some(checkin) = cursor.try_next().await? {
+        let mut data = separator.as_bytes().to_vec();
+        serde_json::to_writer(&mut data, &PublicCheckin::from(checkin)).map_err(io::Error::from)?;
+        archive.write(data).await?;
+
+        separator = ",\n  ";
+    }
+    archive.write("\n]\n").await?;
+
+    Ok(())
+}
+
+/// Columns of checkins.csv, the fields of `CheckinRow` in order. The header
+/// is written on its own so users without check-ins still get it.
+const CHECKIN_CSV_HEADER: [&str; 12] = [
+    "id",
+    "created_at",
+    "updated_at",
+    "mood_rating",
+    "primary_emotion",
+    "secondary_emotions",
+    "intensity",
+    "energy_level",
+    "stress_level",
+    "wellbeing",
+    "notes",
+    "tags",
+];
+
+#[derive(Debug, Serialize)]
+struct CheckinRow {
+    id: String,
+    created_at: String,
+    updated_at: String,
+    mood_rating: u8,
+    primary_emotion: String,
+    /// Separated by semicolons, like the tags.
+    secondary_emotions: String,
+    intensity: u8,
+    energy_level: u8,
+    stress_level: u8,
+    wellbeing: u8,
+    notes: String,
+    tags: String,
+}
+
+impl CheckinRow {
+    fn new(checkin: Checkin, tag_names: &HashMap<ObjectId, String>) -> Self {
+        let tags = checkin
+            .tags
+            .iter()
+            .filter_map(|tag| tag_names.get(tag))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        Self {
+            id: checkin.id.unwrap().to_hex(),
+            created_at: checkin.created_at.to_chrono().to_rfc3339(),
+            updated_at: checkin.updated_at.to_chrono().to_rfc3339(),
+            mood_rating: checkin.mood_rating,
+            primary_emotion: checkin.primary_emotion,
+            secondary_emotions: checkin.secondary_emotions.join(";"),
+            intensity: checkin.intensity,
+            energy_level: checkin.energy_level,
+            stress_level: checkin.stress_level,
+            wellbeing: checkin.wellbeing,
+            notes: checkin.notes.unwrap_or_default(),
+            tags: tags.join(";"),
+        }
+    }
+}
+
+async fn send_checkins_csv(
+    user: &User,
+    tag_names: &HashMap<ObjectId, String>,
+    archive: &ArchiveSender,
+) -> Result<(), Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": 1_i32 })
+        .build();
+    let mut cursor = Checkin::cursor(doc! { "user": &user.id }, options).await?;
+
+    // Each record is written to its own buffer that is handed to the archive
+    // writer
+    let mut header = csv_writer();
+    header
+        .write_record(CHECKIN_CSV_HEADER)
+        .map_err(io::Error::from)?;
+    archive
+        .write(header.into_inner().map_err(|err| err.into_error())?)
+        .await?;
+
+    while let Some(checkin) = cursor.try_next().await? {
+        let mut writer = csv_writer();
+        writer
+            .serialize(CheckinRow::new(checkin, tag_names))
+            .map_err(io::Error::from)?;
+
+        archive
+            .write(writer.into_inner().map_err(|err| err.into_error())?)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn csv_writer() -> csv::Writer<Vec<u8>> {
+    csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new())
+}
+
+fn write_meditation_track(
+    filename: &str,
+    archive: &mut ZipWriter<BufWriter<File>>,
+    options: FileOptions,
+) -> Result<(), Error> {
+    let filename = match PathBuf::from(filename).file_name() {
+        Some(filename) => filename.to_string_lossy().into_owned(),
+        None => return Ok(()),
+    };
+
+    // Tracks whose file is gone are still listed in meditation_tracks.json
+    let mut file = match File::open(PathBuf::from(MUSIC_DIR).join(&filename)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::Io(err)),
+    };
+
+    archive
+        .start_file(format!("meditation_tracks/{}", filename), options)
+        .map_err(io::Error::from)?;
+    io::copy(&mut file, archive)?;
+
+    Ok(())
+}
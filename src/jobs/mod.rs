@@ -0,0 +1,8 @@
+pub mod account_deletion;
+pub mod data_export;
+
+/// Spawns the background jobs, they run for as long as the server does.
+pub fn start() {
+    tokio::spawn(account_deletion::run());
+    tokio::spawn(data_export::run());
+}
@@ -0,0 +1,78 @@
+pub mod outbox;
+pub mod smtp;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::errors::Error;
+use crate::settings::SETTINGS;
+
+/// Emails delivered with the `memory` transport end up here.
+pub static MEMORY_OUTBOX: Lazy<Arc<outbox::MemoryOutbox>> = Lazy::new(Default::default);
+
+static MAILER: Lazy<Arc<dyn Mailer>> = Lazy::new(|| {
+    let settings = &SETTINGS.mailer;
+
+    match settings.transport.as_str() {
+        "smtp" => {
+            let smtp = settings
+                .smtp
+                .as_ref()
+                .expect("Missing mailer.smtp settings for the smtp transport");
+            Arc::new(
+                smtp::SmtpMailer::new(smtp, &settings.from).expect("Failed to setup SMTP mailer"),
+            )
+        }
+        "file" => {
+            let dir = settings
+                .outbox_dir
+                .as_deref()
+                .expect("Missing mailer.outbox_dir setting for the file transport");
+            Arc::new(outbox::FileOutbox::new(dir).expect("Failed to setup file outbox"))
+        }
+        "memory" => MEMORY_OUTBOX.clone(),
+        transport => panic!("Unknown mailer transport {transport}"),
+    }
+});
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    pub fn new<A, B, C>(to: A, subject: B, body: C) -> Self
+    where
+        A: Into<String>,
+        B: Into<String>,
+        C: Into<String>,
+    {
+        Self {
+            to: to.into(),
+            subject: subject.into(),
+            body: body.into(),
+        }
+    }
+}
+
+// Every way of delivering emails implements this trait. The transport used
+// by the application is selected with the `mailer.transport` setting, so
+// tests and local development don't need a mail server.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), Error>;
+}
+
+pub fn mailer() -> &'static dyn Mailer {
+    MAILER.as_ref()
+}
+
+/// Builds a link to the client application.
+pub fn link(path: &str, token: &str) -> String {
+    let base_url = SETTINGS.mailer.link_base_url.trim_end_matches('/');
+    format!("{base_url}{path}?token={token}")
+}
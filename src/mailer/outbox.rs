@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::errors::Error;
+use crate::mailer::{Email, Mailer};
+
+/// Writes every email as a JSON file in a directory instead of sending it.
+pub struct FileOutbox {
+    dir: PathBuf,
+}
+
+impl FileOutbox {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileOutbox {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        let path = self.dir.join(format!("{}.json", Uuid::new_v4()));
+        let contents =
+            serde_json::to_vec_pretty(&email).map_err(|e| Error::SendMail(e.to_string()))?;
+
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| Error::SendMail(e.to_string()))?;
+
+        debug!("Email to {} written to {}", email.to, path.display());
+        Ok(())
+    }
+}
+
+/// Keeps every email in memory instead of sending it.
+#[derive(Default)]
+pub struct MemoryOutbox {
+    emails: Mutex<Vec<Email>>,
+}
+
+impl MemoryOutbox {
+    // Used by tests to inspect sent emails.
+    #[allow(dead_code)]
+    pub fn emails(&self) -> Vec<Email> {
+        self.emails.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryOutbox {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        debug!("Email to {} stored in memory outbox", email.to);
+        self.emails.lock().unwrap().push(email);
+
+        Ok(())
+    }
+}
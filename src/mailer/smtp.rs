@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::errors::Error;
+use crate::mailer::{Email, Mailer};
+use crate::settings::Smtp;
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &Smtp, from: &str) -> Result<Self, Error> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+            .map_err(|e| Error::SendMail(e.to_string()))?
+            .port(settings.port);
+
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = from
+            .parse()
+            .map_err(|_| Error::SendMail(format!("Invalid sender {from}")))?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        let to: Mailbox = email
+            .to
+            .parse()
+            .map_err(|_| Error::SendMail(format!("Invalid recipient {}", email.to)))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| Error::SendMail(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| Error::SendMail(e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for AccountDeletion {}
+
+// Record of an erased account, kept to prove the erasure happened. It must
+// not hold anything that identifies the user.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "deleted_at": 1 }"#))]
+pub struct AccountDeletion {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// When the account was created, truncated to the day.
+    pub account_created_on: Date,
+    pub scheduled_at: Date,
+    pub erased: ErasedCounts,
+    pub deleted_at: Date,
+}
+
+/// Number of documents and files removed with the account.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ErasedCounts {
+    pub checkins: u64,
+    #[serde(default)]
+    pub tags: u64,
+    pub cats: u64,
+    pub meditation_tracks: u64,
+    pub data_exports: u64,
+    pub api_tokens: u64,
+    pub sessions: u64,
+    pub refresh_tokens: u64,
+    pub one_time_tokens: u64,
+}
+
+impl AccountDeletion {
+    pub fn new(account_created_at: Date, scheduled_at: Date, erased: ErasedCounts) -> Self {
+        let created_on = account_created_at.to_chrono().date_naive();
+        Self {
+            id: None,
+            account_created_on: created_on.and_hms_opt(0, 0, 0).unwrap().and_utc().into(),
+            scheduled_at,
+            erased,
+            deleted_at: date::now(),
+        }
+    }
+}
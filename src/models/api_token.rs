@@ -0,0 +1,140 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+
+impl ModelExt for ApiToken {}
+
+/// Prefix of personal access tokens, telling them apart from JWTs.
+pub const TOKEN_PREFIX: &str = "mmp_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "checkins:read")]
+    CheckinsRead,
+    #[serde(rename = "checkins:write")]
+    CheckinsWrite,
+    #[serde(rename = "meditation:generate")]
+    MeditationGenerate,
+}
+
+// Long lived tokens users create for their scripts and integrations. They
+// only grant access to the routes covered by their scopes.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(
+        keys = r#"doc!{ "token_hash": 1 }"#,
+        options = r#"doc!{ "unique": true }"#
+    ),
+    index(keys = r#"doc!{ "user": 1, "created_at": -1 }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct ApiToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<Scope>,
+    pub token_hash: String,
+    /// The first characters of the token, helping users recognize it.
+    pub token_hint: String,
+    /// Tokens without expiration date are valid until revoked.
+    pub expires_at: Option<Date>,
+    pub last_used_at: Option<Date>,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+impl ApiToken {
+    pub fn new(
+        user: ObjectId,
+        name: String,
+        scopes: Vec<Scope>,
+        token: &str,
+        expires_at: Option<Date>,
+    ) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            user,
+            name,
+            scopes,
+            token_hash: opaque_token::hash(token),
+            token_hint: token.chars().take(TOKEN_PREFIX.len() + 4).collect(),
+            expires_at,
+            last_used_at: None,
+            updated_at: now,
+            created_at: now,
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicApiToken {
+    #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub token_hint: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: Date,
+}
+
+impl From<ApiToken> for PublicApiToken {
+    fn from(api_token: ApiToken) -> Self {
+        Self {
+            id: api_token.id.unwrap(),
+            name: api_token.name,
+            scopes: api_token.scopes,
+            token_hint: api_token.token_hint,
+            expires_at: api_token
+                .expires_at
+                .map(|date| date.to_chrono().to_rfc3339()),
+            last_used_at: api_token
+                .last_used_at
+                .map(|date| date.to_chrono().to_rfc3339()),
+            created_at: api_token.created_at,
+        }
+    }
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Generates a new raw token, only its hash is persisted.
+pub fn generate() -> String {
+    format!("{}{}", TOKEN_PREFIX, opaque_token::generate())
+}
+
+/// Looks up a token that is still valid, recording that it was used.
+pub async fn authenticate(token: &str) -> Result<Option<ApiToken>, Error> {
+    let now = date::now();
+    <ApiToken as ModelExt>::find_one_and_update(
+        doc! {
+            "token_hash": opaque_token::hash(token),
+            "$or": [{ "expires_at": null }, { "expires_at": { "$gt": now } }]
+        },
+        doc! { "$set": { "last_used_at": now } },
+    )
+    .await
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId, Document};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for AuditEvent {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    Signup,
+    SigninSucceeded,
+    SigninFailed,
+    UserLocked,
+    UserUnlocked,
+    PasswordChanged,
+    PasswordReset,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    SessionRevoked,
+    AllSessionsRevoked,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    DataExportRequested,
+    DataExportDownloaded,
+    AccountDeletionRequested,
+    AccountDeletionCancelled,
+    AccountErased,
+}
+
+// Security relevant events. The collection is append-only, events are never
+// removed. The only update is the pseudonymisation of the events of an erased
+// account, which keeps the history without what identifies its owner.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(keys = r#"doc!{ "created_at": -1 }"#),
+    index(keys = r#"doc!{ "actor": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "subject": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "kind": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "ip": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "request_id": 1 }"#)
+)]
+pub struct AuditEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub kind: AuditEventKind,
+    /// User who performed the action, unknown for failed signins with an
+    /// unregistered email and for background jobs.
+    pub actor: Option<ObjectId>,
+    /// User the action was performed on.
+    pub subject: Option<ObjectId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub details: Option<Document>,
+    pub created_at: Date,
+}
+
+impl AuditEvent {
+    pub fn new(kind: AuditEventKind) -> Self {
+        Self {
+            id: None,
+            kind,
+            actor: None,
+            subject: None,
+            ip: None,
+            user_agent: None,
+            request_id: None,
+            details: None,
+            created_at: date::now(),
+        }
+    }
+
+    /// An event of a user acting on their own account.
+    pub fn for_user(kind: AuditEventKind, user: ObjectId) -> Self {
+        Self::new(kind).actor(user).subject(user)
+    }
+
+    pub fn actor(mut self, actor: ObjectId) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    pub fn subject(mut self, subject: ObjectId) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    pub fn details(mut self, details: Document) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// Detaches the events of an erased user from them. Their ids are replaced by
+/// a pseudonym shared by all of their events, and the request origin and
+/// email are removed.
+pub async fn pseudonymise(user: &ObjectId, email: &str) -> Result<(), Error> {
+    let pseudonym = ObjectId::new();
+
+    AuditEvent::update_many(
+        doc! { "$or": [
+            { "actor": user },
+            { "subject": user },
+            { "details.email": email }
+        ] },
+        doc! { "$unset": { "ip": "", "user_agent": "", "details.email": "" } },
+        None,
+    )
+    .await?;
+    AuditEvent::update_many(
+        doc! { "actor": user },
+        doc! { "$set": { "actor": pseudonym } },
+        None,
+    )
+    .await?;
+    AuditEvent::update_many(
+        doc! { "subject": user },
+        doc! { "$set": { "subject": pseudonym } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
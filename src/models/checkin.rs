@@ -1,21 +1,3 @@
-pub const EMOTION_JOY: &str = "joy";
-pub const EMOTION_SADNESS: &str = "sadness";
-pub const EMOTION_ANGER: &str = "anger";
-pub const EMOTION_FEAR: &str = "fear";
-pub const EMOTION_DISGUST: &str = "disgust";
-pub const EMOTION_SURPRISE: &str = "surprise";
-
-pub fn valid_emotions() -> Vec<&'static str> {
-    vec![
-        EMOTION_JOY,
-        EMOTION_SADNESS,
-        EMOTION_ANGER,
-        EMOTION_FEAR,
-        EMOTION_DISGUST,
-        EMOTION_SURPRISE,
-    ]
-}
-
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
 use serde::{Deserialize, Serialize};
@@ -30,19 +12,27 @@ use crate::utils::models::ModelExt;
 impl ModelExt for Checkin {}
 
 #[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
-#[model(index(keys = r#"doc!{ "user": 1, "created_at": 1 }"#))]
+#[model(
+    index(keys = r#"doc!{ "user": 1, "created_at": 1 }"#),
+    index(keys = r#"doc!{ "user": 1, "primary_emotion": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "user": 1, "tags": 1, "created_at": -1 }"#)
+)]
 pub struct Checkin {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub user: ObjectId,
-    
+
     // Core mood data
     #[validate(range(min = 1, max = 5))]
     pub mood_rating: u8,
+    /// A core emotion of the emotion wheel.
     pub primary_emotion: String,
+    /// Any other emotions of the wheel felt along with the primary one.
+    #[serde(default)]
+    pub secondary_emotions: Vec<String>,
     #[validate(range(min = 1, max = 5))]
     pub intensity: u8,
-    
+
     // Well-being metrics
     #[validate(range(min = 1, max = 5))]
     pub energy_level: u8,
@@ -50,25 +40,32 @@ pub struct Checkin {
     pub stress_level: u8,
     #[validate(range(min = 1, max = 5))]
     pub wellbeing: u8,
-    
+
     // Note/journal field - optional
     pub notes: Option<String>,
-    
+
+    /// Tags of the user's vocabulary.
+    #[serde(default)]
+    pub tags: Vec<ObjectId>,
+
     // Timestamps
     pub updated_at: Date,
     pub created_at: Date,
 }
 
 impl Checkin {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        user: ObjectId, 
+        user: ObjectId,
         mood_rating: u8,
         primary_emotion: String,
+        secondary_emotions: Vec<String>,
         intensity: u8,
         energy_level: u8,
         stress_level: u8,
         wellbeing: u8,
         notes: Option<String>,
+        tags: Vec<ObjectId>,
     ) -> Self {
         let now = date::now();
         Self {
@@ -76,11 +73,13 @@ impl Checkin {
             user,
             mood_rating,
             primary_emotion,
+            secondary_emotions,
             intensity,
             energy_level,
             stress_level,
             wellbeing,
             notes,
+            tags,
             updated_at: now,
             created_at: now,
         }
@@ -95,11 +94,13 @@ pub struct PublicCheckin {
     pub user: ObjectId,
     pub mood_rating: u8,
     pub primary_emotion: String,
+    pub secondary_emotions: Vec<String>,
     pub intensity: u8,
     pub energy_level: u8,
     pub stress_level: u8,
     pub wellbeing: u8,
     pub notes: Option<String>,
+    pub tags: Vec<String>,
     #[serde(with = "bson_datetime_as_rfc3339_string")]
     pub updated_at: Date,
     #[serde(with = "bson_datetime_as_rfc3339_string")]
@@ -113,13 +114,15 @@ impl From<Checkin> for PublicCheckin {
             user: checkin.user,
             mood_rating: checkin.mood_rating,
             primary_emotion: checkin.primary_emotion,
+            secondary_emotions: checkin.secondary_emotions,
             intensity: checkin.intensity,
             energy_level: checkin.energy_level,
             stress_level: checkin.stress_level,
             wellbeing: checkin.wellbeing,
             notes: checkin.notes,
+            tags: checkin.tags.into_iter().map(ObjectId::to_hex).collect(),
             updated_at: checkin.updated_at,
             created_at: checkin.created_at,
         }
     }
-}
\ No newline at end of file
+}
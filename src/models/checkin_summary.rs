@@ -0,0 +1,415 @@
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{debug, error};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId, Document};
+use wither::mongodb::options::UpdateOptions;
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::models::checkin::Checkin;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for CheckinSummary {}
+
+// Check-ins per local day of a user, kept up to date as check-ins are created,
+// edited and removed so streaks don't need a scan of every check-in. Days are
+// keyed by their `YYYY-MM-DD` date in the summary's time zone. A summary that
+// is missing or was made for another time zone than the user's current one is
+// rebuilt from the check-ins when it's loaded, and increments are only
+// applied to a summary made for the time zone they were computed in. Every
+// change bumps the version, so a rebuild can tell whether check-ins changed
+// while it was counting them.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "user": 1 }"#, options = r#"doc!{ "unique": true }"#))]
+pub struct CheckinSummary {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub timezone: String,
+    pub days: BTreeMap<String, DaySummary>,
+    #[serde(default)]
+    pub version: i64,
+    /// Whether the days account for every check-in, they don't while the
+    /// summary is rebuilt.
+    #[serde(default)]
+    pub complete: bool,
+    pub updated_at: Date,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DaySummary {
+    pub count: i64,
+    pub mood_total: i64,
+}
+
+impl DaySummary {
+    pub fn average_mood(&self) -> f64 {
+        self.mood_total as f64 / self.count as f64
+    }
+}
+
+impl CheckinSummary {
+    /// Days with at least one check-in, oldest first.
+    pub fn active_days(&self) -> impl DoubleEndedIterator<Item = (NaiveDate, &DaySummary)> {
+        self.days
+            .iter()
+            .filter(|(_, day)| day.count > 0)
+            .filter_map(|(key, day)| Some((NaiveDate::parse_from_str(key, "%Y-%m-%d").ok()?, day)))
+    }
+
+    pub fn last_checkin_date(&self) -> Option<NaiveDate> {
+        self.active_days().next_back().map(|(day, _)| day)
+    }
+
+    /// Consecutive days with a check-in up to today. A streak isn't broken
+    /// before the day without a check-in is over, so one ending yesterday is
+    /// still current.
+    pub fn current_streak(&self, today: NaiveDate) -> u32 {
+        let mut days = self
+            .active_days()
+            .rev()
+            .map(|(day, _)| day)
+            .skip_while(|day| *day > today);
+
+        let mut last = match days.next() {
+            Some(day) if day == today || Some(day) == today.pred_opt() => day,
+            _ => return 0,
+        };
+
+        let mut streak = 1;
+        for day in days {
+            if Some(day) != last.pred_opt() {
+                break;
+            }
+            streak += 1;
+            last = day;
+        }
+
+        streak
+    }
+
+    pub fn longest_streak(&self) -> u32 {
+        let mut longest = 0;
+        let mut streak = 0;
+        let mut previous: Option<NaiveDate> = None;
+
+        for (day, _) in self.active_days() {
+            streak = match previous {
+                Some(previous) if previous.succ_opt() == Some(day) => streak + 1,
+                _ => 1,
+            };
+            longest = longest.max(streak);
+            previous = Some(day);
+        }
+
+        longest
+    }
+}
+
+fn day_key(tz: Tz, created_at: Date) -> String {
+    created_at
+        .to_chrono()
+        .with_timezone(&tz)
+        .date_naive()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// Loads the summary of a user, rebuilding it when it's missing or was made
+/// for another time zone.
+pub async fn load(user: &ObjectId, tz: Tz) -> Result<CheckinSummary, Error> {
+    let summary = <CheckinSummary as ModelExt>::find_one(doc! { "user": user }, None).await?;
+
+    match summary {
+        Some(summary) if summary.complete && summary.timezone == tz.name() => Ok(summary),
+        _ => rebuild(user, tz).await,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DayRow {
+    #[serde(rename = "_id")]
+    day: String,
+    count: i64,
+    mood_total: i64,
+}
+
+/// How many times a rebuild starts over when check-ins change while it runs.
+const MAX_REBUILD_ATTEMPTS: u32 = 3;
+
+// The summary is switched to the time zone and marked incomplete before the
+// check-ins are counted, so increments made in the meantime land on it and
+// bump its version. The days are only stored when the version is unchanged,
+// otherwise the rebuild starts over. When it keeps losing the summary stays
+// incomplete and the next load rebuilds it again.
+async fn rebuild(user: &ObjectId, tz: Tz) -> Result<CheckinSummary, Error> {
+    debug!("Rebuilding checkin summary");
+    let mut attempt = 1;
+
+    loop {
+        let options = UpdateOptions::builder().upsert(true).build();
+        CheckinSummary::update_one(
+            doc! { "user": user },
+            doc! {
+                "$set": { "timezone": tz.name(), "complete": false },
+                "$setOnInsert": { "days": {}, "updated_at": date::now() },
+                "$inc": { "version": 1 },
+            },
+            options,
+        )
+        .await?;
+
+        // Increments made before the version is read are for check-ins that
+        // already exist, so they are counted below
+        let version = <CheckinSummary as ModelExt>::find_one(doc! { "user": user }, None)
+            .await?
+            .map(|summary| summary.version)
+            .unwrap_or_default();
+
+        let summary = CheckinSummary {
+            id: None,
+            user: *user,
+            timezone: tz.name().to_string(),
+            days: count_days(user, tz).await?,
+            version,
+            complete: true,
+            updated_at: date::now(),
+        };
+
+        let result = CheckinSummary::update_one(
+            doc! { "user": user, "version": version },
+            doc! {
+                "$set": {
+                    "days": bson::to_bson(&summary.days).unwrap(),
+                    "complete": true,
+                    "updated_at": summary.updated_at,
+                }
+            },
+            None,
+        )
+        .await?;
+
+        if result.matched_count == 1 || attempt == MAX_REBUILD_ATTEMPTS {
+            return Ok(summary);
+        }
+
+        debug!("Check-ins changed during the summary rebuild, starting over");
+        attempt += 1;
+    }
+}
+
+async fn count_days(user: &ObjectId, tz: Tz) -> Result<BTreeMap<String, DaySummary>, Error> {
+    let pipeline = vec![
+        doc! { "$match": { "user": user } },
+        doc! {
+            "$group": {
+                "_id": {
+                    "$dateToString": {
+                        "format": "%Y-%m-%d",
+                        "date": "$created_at",
+                        "timezone": tz.name(),
+                    }
+                },
+                "count": { "$sum": 1 },
+                "mood_total": { "$sum": "$mood_rating" },
+            }
+        },
+    ];
+
+    let days = Checkin::aggregate::<DayRow>(pipeline)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let day = DaySummary {
+                count: row.count,
+                mood_total: row.mood_total,
+            };
+            (row.day, day)
+        })
+        .collect();
+
+    Ok(days)
+}
+
+// The summary follows the check-ins on a best effort basis, the check-in
+// itself is already written. When an increment fails the summary is dropped
+// so it's rebuilt on the next load instead of staying wrong.
+async fn apply(user: &ObjectId, tz: Tz, update: Document) {
+    let query = doc! { "user": user, "timezone": tz.name() };
+    if let Err(err) = CheckinSummary::update_one(query, update, None).await {
+        error!("Failed to update checkin summary: {}", err);
+
+        if let Err(err) = CheckinSummary::delete_one(doc! { "user": user }).await {
+            error!("Failed to drop stale checkin summary: {}", err);
+        }
+    }
+}
+
+pub async fn record_created(tz: Tz, checkin: &Checkin) {
+    let key = day_key(tz, checkin.created_at);
+    let update = doc! {
+        "$inc": {
+            format!("days.{}.count", key): 1,
+            format!("days.{}.mood_total", key): checkin.mood_rating as i32,
+            "version": 1,
+        },
+        "$set": { "updated_at": date::now() },
+    };
+
+    apply(&checkin.user, tz, update).await;
+}
+
+pub async fn record_mood_changed(tz: Tz, checkin: &Checkin, previous_mood: u8) {
+    let delta = checkin.mood_rating as i32 - previous_mood as i32;
+    if delta == 0 {
+        return;
+    }
+
+    let key = day_key(tz, checkin.created_at);
+    let update = doc! {
+        "$inc": {
+            format!("days.{}.mood_total", key): delta,
+            "version": 1,
+        },
+        "$set": { "updated_at": date::now() },
+    };
+
+    apply(&checkin.user, tz, update).await;
+}
+
+pub async fn record_removed(tz: Tz, checkin: &Checkin) {
+    let key = day_key(tz, checkin.created_at);
+    let update = doc! {
+        "$inc": {
+            format!("days.{}.count", key): -1,
+            format!("days.{}.mood_total", key): -(checkin.mood_rating as i32),
+            "version": 1,
+        },
+        "$set": { "updated_at": date::now() },
+    };
+
+    apply(&checkin.user, tz, update).await;
+
+    // Days left without check-ins are dropped from the summary
+    let emptied = doc! {
+        "user": &checkin.user,
+        "timezone": tz.name(),
+        format!("days.{}.count", key): { "$lte": 0 },
+    };
+    let update = doc! {
+        "$unset": { format!("days.{}", key): "" },
+        "$inc": { "version": 1 },
+    };
+    if let Err(err) = CheckinSummary::update_one(emptied, update, None).await {
+        error!("Failed to remove empty day from checkin summary: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(days: &[&str]) -> CheckinSummary {
+        let days = days
+            .iter()
+            .map(|day| {
+                let summary = DaySummary {
+                    count: 1,
+                    mood_total: 5,
+                };
+                (day.to_string(), summary)
+            })
+            .collect();
+
+        CheckinSummary {
+            id: None,
+            user: ObjectId::new(),
+            timezone: "UTC".to_string(),
+            days,
+            version: 0,
+            complete: true,
+            updated_at: date::now(),
+        }
+    }
+
+    fn day(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn streaks_are_zero_without_checkins() {
+        let summary = summary(&[]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 0);
+        assert_eq!(summary.longest_streak(), 0);
+    }
+
+    #[test]
+    fn current_streak_includes_today() {
+        let summary = summary(&["2024-03-08", "2024-03-09", "2024-03-10"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 3);
+    }
+
+    #[test]
+    fn current_streak_ending_yesterday_is_kept() {
+        let summary = summary(&["2024-03-08", "2024-03-09"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 2);
+    }
+
+    #[test]
+    fn current_streak_ending_before_yesterday_is_broken() {
+        let summary = summary(&["2024-03-07", "2024-03-08"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 0);
+    }
+
+    #[test]
+    fn current_streak_stops_at_gaps() {
+        let summary = summary(&["2024-03-05", "2024-03-06", "2024-03-09", "2024-03-10"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 2);
+    }
+
+    #[test]
+    fn current_streak_ignores_future_days() {
+        let summary = summary(&["2024-03-09", "2024-03-10", "2024-03-11", "2024-03-13"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 2);
+    }
+
+    #[test]
+    fn current_streak_with_only_future_days_is_zero() {
+        let summary = summary(&["2024-03-11", "2024-03-12"]);
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 0);
+    }
+
+    #[test]
+    fn longest_streak_spans_gaps() {
+        let summary = summary(&[
+            "2024-02-28",
+            "2024-02-29",
+            "2024-03-01",
+            "2024-03-05",
+            "2024-03-06",
+        ]);
+
+        assert_eq!(summary.longest_streak(), 3);
+    }
+
+    #[test]
+    fn emptied_days_break_streaks() {
+        let mut summary = summary(&["2024-03-08", "2024-03-09", "2024-03-10"]);
+        summary.days.get_mut("2024-03-09").unwrap().count = 0;
+
+        assert_eq!(summary.current_streak(day("2024-03-10")), 1);
+        assert_eq!(summary.longest_streak(), 1);
+    }
+}
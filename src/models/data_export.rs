@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for DataExport {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+// Archive of the personal data of a user, built in the background. Once it
+// is ready the user gets a download link by email, the archive is removed
+// when the link expires.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(keys = r#"doc!{ "user": 1, "created_at": -1 }"#),
+    index(keys = r#"doc!{ "download_token_hash": 1 }"#),
+    index(keys = r#"doc!{ "expires_at": 1 }"#)
+)]
+pub struct DataExport {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub status: ExportStatus,
+    pub download_token_hash: Option<String>,
+    pub expires_at: Date,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+impl DataExport {
+    pub fn new(user: ObjectId) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            user,
+            status: ExportStatus::Pending,
+            download_token_hash: None,
+            expires_at: download_expires_at(now),
+            updated_at: now,
+            created_at: now,
+        }
+    }
+
+    /// Where the archive of the export is written.
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(&SETTINGS.data_export.dir).join(format!("{}.zip", self.id.unwrap()))
+    }
+}
+
+pub fn download_expires_at(now: Date) -> Date {
+    let ttl = chrono::Duration::seconds(SETTINGS.data_export.download_ttl);
+    (now.to_chrono() + ttl).into()
+}
+
+/// Removes an export along with its archive.
+pub async fn remove(export: &DataExport) -> Result<(), Error> {
+    match tokio::fs::remove_file(export.path()).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(Error::Io(err)),
+    }
+
+    DataExport::delete_one(doc! { "_id": export.id }).await?;
+
+    Ok(())
+}
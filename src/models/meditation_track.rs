@@ -0,0 +1,96 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for MeditationTrack {}
+
+/// Directory where generated meditation audio files are stored.
+pub const MUSIC_DIR: &str = "./meditation_music";
+
+// Generated audio files are stored on disk, this records who they belong to
+// so they can be removed along with the account, and the session they were
+// generated for.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "user": 1, "created_at": 1 }"#))]
+pub struct MeditationTrack {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub filename: String,
+    /// Missing for the tracks generated before sessions were recorded.
+    #[serde(default)]
+    pub session: Option<MeditationSession>,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+/// What the user asked for when generating a track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeditationSession {
+    /// In minutes.
+    pub duration: u32,
+    pub meditation_type: String,
+    pub music_atmosphere: String,
+    pub focus_area: String,
+    pub background: String,
+}
+
+impl MeditationTrack {
+    pub fn new(user: ObjectId, filename: String, session: MeditationSession) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            user,
+            filename,
+            session: Some(session),
+            updated_at: now,
+            created_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicMeditationTrack {
+    #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub filename: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: Date,
+}
+
+impl From<MeditationTrack> for PublicMeditationTrack {
+    fn from(track: MeditationTrack) -> Self {
+        Self {
+            id: track.id.unwrap(),
+            filename: track.filename,
+            created_at: track.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicMeditationSession {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub track: ObjectId,
+    #[serde(flatten)]
+    pub session: MeditationSession,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: Date,
+}
+
+impl PublicMeditationSession {
+    pub fn new(track: &MeditationTrack) -> Option<Self> {
+        Some(Self {
+            track: track.id.unwrap(),
+            session: track.session.clone()?,
+            created_at: track.created_at,
+        })
+    }
+}
@@ -1,14 +1,40 @@
+pub mod account_deletion;
+pub mod api_token;
+pub mod audit_event;
 pub mod cat;
-pub mod user;
 pub mod checkin;
+pub mod checkin_summary;
+pub mod data_export;
+pub mod meditation_track;
+pub mod oidc_state;
+pub mod one_time_token;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod session;
+pub mod signin_throttle;
+pub mod tag;
+pub mod user;
 
-use crate::utils::models::ModelExt;
 use crate::errors::Error;
+use crate::utils::models::ModelExt;
 
 pub async fn sync_indexes() -> Result<(), Error> {
     user::User::sync_indexes().await?;
     cat::Cat::sync_indexes().await?;
+    api_token::ApiToken::sync_indexes().await?;
+    audit_event::AuditEvent::sync_indexes().await?;
+    account_deletion::AccountDeletion::sync_indexes().await?;
+    meditation_track::MeditationTrack::sync_indexes().await?;
     checkin::Checkin::sync_indexes().await?;
+    checkin_summary::CheckinSummary::sync_indexes().await?;
+    data_export::DataExport::sync_indexes().await?;
+    oidc_state::OidcState::sync_indexes().await?;
+    one_time_token::OneTimeToken::sync_indexes().await?;
+    refresh_token::RefreshToken::sync_indexes().await?;
+    revoked_token::RevokedToken::sync_indexes().await?;
+    session::Session::sync_indexes().await?;
+    signin_throttle::SigninThrottle::sync_indexes().await?;
+    tag::Tag::sync_indexes().await?;
 
     Ok(())
-}
\ No newline at end of file
+}
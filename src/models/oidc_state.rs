@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for OidcState {}
+
+// Pending OpenID Connect authorizations. They hold what is needed to
+// complete the flow once the user comes back from the provider, and expire
+// if that doesn't happen in a few minutes.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(
+        keys = r#"doc!{ "state_hash": 1 }"#,
+        options = r#"doc!{ "unique": true }"#
+    ),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct OidcState {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub provider: String,
+    pub state_hash: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_at: Date,
+    pub created_at: Date,
+}
+
+impl OidcState {
+    pub fn new(provider: String, state_hash: String, nonce: String, code_verifier: String) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            provider,
+            state_hash,
+            nonce,
+            code_verifier,
+            expires_at: (now.to_chrono() + chrono::Duration::minutes(10)).into(),
+            created_at: now,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+
+impl ModelExt for OneTimeToken {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+    EmailChange,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailVerification => "email_verification",
+            TokenPurpose::EmailChange => "email_change",
+        }
+    }
+}
+
+// Single use tokens sent to users by email. Only a hash of the token is
+// stored, and documents are removed by MongoDB once they expire.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(
+        keys = r#"doc!{ "token_hash": 1 }"#,
+        options = r#"doc!{ "unique": true }"#
+    ),
+    index(keys = r#"doc!{ "user": 1, "purpose": 1 }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct OneTimeToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub purpose: TokenPurpose,
+    pub token_hash: String,
+    pub expires_at: Date,
+    pub used_at: Option<Date>,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+impl OneTimeToken {
+    pub fn new(user: ObjectId, purpose: TokenPurpose, token_hash: String, ttl: i64) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            user,
+            purpose,
+            token_hash,
+            expires_at: (now.to_chrono() + chrono::Duration::seconds(ttl)).into(),
+            used_at: None,
+            updated_at: now,
+            created_at: now,
+        }
+    }
+}
+
+/// Issues a token valid for `ttl` seconds, discarding any unused token the
+/// user already had for the same purpose. Returns the raw token.
+pub async fn issue(user: &ObjectId, purpose: TokenPurpose, ttl: i64) -> Result<String, Error> {
+    <OneTimeToken as ModelExt>::delete_many(doc! {
+        "user": user,
+        "purpose": purpose.as_str(),
+        "used_at": null
+    })
+    .await?;
+
+    let token = opaque_token::generate();
+    let one_time_token = OneTimeToken::new(*user, purpose, opaque_token::hash(&token), ttl);
+    OneTimeToken::create(one_time_token).await?;
+
+    Ok(token)
+}
+
+/// Looks a token up without using it, so the rest of a request can be
+/// checked before the token is consumed.
+pub async fn find_valid(token: &str, purpose: TokenPurpose) -> Result<OneTimeToken, Error> {
+    let one_time_token = <OneTimeToken as ModelExt>::find_one(
+        doc! {
+            "token_hash": opaque_token::hash(token),
+            "purpose": purpose.as_str(),
+            "used_at": null,
+            "expires_at": { "$gt": date::now() }
+        },
+        None,
+    )
+    .await?;
+
+    one_time_token
+        .ok_or_else(|| Error::bad_request_with_message("Invalid or expired token".to_string()))
+}
+
+/// Marks a token as used, failing if it is unknown, expired or was already
+/// used.
+pub async fn consume(token: &str, purpose: TokenPurpose) -> Result<OneTimeToken, Error> {
+    let now = date::now();
+    let one_time_token = <OneTimeToken as ModelExt>::find_one_and_update(
+        doc! {
+            "token_hash": opaque_token::hash(token),
+            "purpose": purpose.as_str(),
+            "used_at": null,
+            "expires_at": { "$gt": now }
+        },
+        doc! { "$set": { "used_at": now, "updated_at": now } },
+    )
+    .await?;
+
+    one_time_token
+        .ok_or_else(|| Error::bad_request_with_message("Invalid or expired token".to_string()))
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::errors::{AuthenticateError, Error};
+use crate::models::session;
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+
+impl ModelExt for RefreshToken {}
+
+// Every refresh token belongs to a family, which is started on signin and
+// shared by all the tokens obtained by rotating it. The family is the id of
+// the session started on signin. Presenting a token that was already rotated
+// means it leaked, so the whole family gets revoked.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(
+        keys = r#"doc!{ "token_hash": 1 }"#,
+        options = r#"doc!{ "unique": true }"#
+    ),
+    index(keys = r#"doc!{ "family": 1 }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct RefreshToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub family: ObjectId,
+    pub token_hash: String,
+    pub expires_at: Date,
+    pub revoked_at: Option<Date>,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+impl RefreshToken {
+    pub fn new(user: ObjectId, family: ObjectId, token_hash: String) -> Self {
+        let now = date::now();
+        let ttl = chrono::Duration::seconds(SETTINGS.auth.refresh_token_ttl);
+        Self {
+            id: None,
+            user,
+            family,
+            token_hash,
+            expires_at: (now.to_chrono() + ttl).into(),
+            revoked_at: None,
+            updated_at: now,
+            created_at: now,
+        }
+    }
+}
+
+/// Issues a refresh token for the given user, starting the token family of
+/// the given session. Returns the raw token, only its hash is persisted.
+pub async fn issue(user: &ObjectId, session: &ObjectId) -> Result<String, Error> {
+    issue_in_family(user, *session).await
+}
+
+/// Exchanges a refresh token for a new one of the same family. Returns the
+/// owner of the token, its family and the new raw token.
+pub async fn rotate(token: &str) -> Result<(ObjectId, ObjectId, String), Error> {
+    let token_hash = opaque_token::hash(token);
+    let now = date::now();
+
+    let current = <RefreshToken as ModelExt>::find_one_and_update(
+        doc! {
+            "token_hash": &token_hash,
+            "revoked_at": null,
+            "expires_at": { "$gt": now }
+        },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+    )
+    .await?;
+
+    let current = match current {
+        Some(current) => current,
+        None => {
+            let reused = <RefreshToken as ModelExt>::find_one(
+                doc! { "token_hash": &token_hash, "revoked_at": { "$ne": null } },
+                None,
+            )
+            .await?;
+
+            if let Some(reused) = reused {
+                warn!("Refresh token reuse detected, revoking token family");
+                revoke_family(&reused.family).await?;
+                session::revoke(&reused.family, &reused.user).await?;
+            }
+
+            return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+        }
+    };
+
+    let token = issue_in_family(&current.user, current.family).await?;
+
+    Ok((current.user, current.family, token))
+}
+
+pub async fn revoke_family(family: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    RefreshToken::update_many(
+        doc! { "family": family, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes the family of the given refresh token, as long as it belongs to
+/// the given user.
+pub async fn revoke(token: &str, user: &ObjectId) -> Result<(), Error> {
+    let token_hash = opaque_token::hash(token);
+    let refresh_token =
+        <RefreshToken as ModelExt>::find_one(doc! { "token_hash": token_hash, "user": user }, None)
+            .await?;
+
+    if let Some(refresh_token) = refresh_token {
+        revoke_family(&refresh_token.family).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn revoke_all(user: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    RefreshToken::update_many(
+        doc! { "user": user, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes all the refresh tokens of the user except the ones of the given
+/// family.
+pub async fn revoke_all_except(user: &ObjectId, family: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    RefreshToken::update_many(
+        doc! { "user": user, "family": { "$ne": family }, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn issue_in_family(user: &ObjectId, family: ObjectId) -> Result<String, Error> {
+    let token = opaque_token::generate();
+    let refresh_token = RefreshToken::new(*user, family, opaque_token::hash(&token));
+    RefreshToken::create(refresh_token).await?;
+
+    Ok(token)
+}
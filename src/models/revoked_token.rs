@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::UpdateOptions;
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for RevokedToken {}
+
+// Access tokens revoked before their expiration, identified by their `jti`
+// claim. Documents are removed by MongoDB once the token would have expired
+// anyway.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(keys = r#"doc!{ "jti": 1 }"#, options = r#"doc!{ "unique": true }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct RevokedToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub jti: String,
+    pub expires_at: Date,
+    pub created_at: Date,
+}
+
+/// Revokes the access token with the given `jti` and `exp` claims. Revoking
+/// a token twice, for instance with concurrent signouts, is harmless.
+pub async fn revoke(jti: &str, exp: usize) -> Result<(), Error> {
+    let expires_at = Date::from_millis(exp as i64 * 1000);
+    let options = UpdateOptions::builder().upsert(true).build();
+    RevokedToken::update_one(
+        doc! { "jti": jti },
+        doc! {
+            "$setOnInsert": {
+                "expires_at": expires_at,
+                "created_at": date::now()
+            }
+        },
+        options,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn is_revoked(jti: &str) -> Result<bool, Error> {
+    RevokedToken::exists(doc! { "jti": jti }).await
+}
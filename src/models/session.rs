@@ -0,0 +1,232 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::UpdateOptions;
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::models::refresh_token;
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for Session {}
+
+/// How often `last_seen_at` is updated, in seconds.
+const LAST_SEEN_RESOLUTION: i64 = 60;
+
+// A session is started on every signin and lasts as long as its refresh
+// tokens, whose family is the session id. Access tokens carry the session id
+// in their `sid` claim so they stop working once the session is revoked.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(keys = r#"doc!{ "user": 1, "last_seen_at": -1 }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_seen_at: Date,
+    pub revoked_at: Option<Date>,
+    pub expires_at: Date,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+/// What is known about the device a user signs in from.
+#[derive(Debug, Clone, Default)]
+pub struct Device {
+    pub name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+impl Device {
+    pub fn new(name: Option<String>, user_agent: Option<String>, ip: Option<IpAddr>) -> Self {
+        let name = name
+            .map(|name| name.trim().chars().take(100).collect::<String>())
+            .filter(|name| !name.is_empty());
+
+        Self {
+            name,
+            user_agent,
+            ip,
+        }
+    }
+}
+
+impl Session {
+    pub fn new(user: ObjectId, device: Device) -> Self {
+        let now = date::now();
+        Self {
+            id: None,
+            user,
+            device_name: device.name,
+            user_agent: device.user_agent,
+            ip: device.ip.map(|ip| ip.to_string()),
+            last_seen_at: now,
+            revoked_at: None,
+            expires_at: expires_at(now),
+            updated_at: now,
+            created_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicSession {
+    #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Whether this is the session of the token used for the request.
+    pub current: bool,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub last_seen_at: Date,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: Date,
+}
+
+impl PublicSession {
+    pub fn new(session: Session, current: Option<ObjectId>) -> Self {
+        Self {
+            id: session.id.unwrap(),
+            current: session.id == current,
+            device_name: session.device_name,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            last_seen_at: session.last_seen_at,
+            created_at: session.created_at,
+        }
+    }
+}
+
+fn expires_at(now: Date) -> Date {
+    let ttl = chrono::Duration::seconds(SETTINGS.auth.refresh_token_ttl);
+    (now.to_chrono() + ttl).into()
+}
+
+/// Starts a session for the given user. Returns the session id.
+pub async fn start(user: &ObjectId, device: Device) -> Result<ObjectId, Error> {
+    let session = Session::create(Session::new(*user, device)).await?;
+
+    Ok(session.id.unwrap())
+}
+
+/// Extends a session when its refresh token is rotated. Refresh token
+/// families started before sessions existed get a session on their first
+/// rotation.
+pub async fn extend(id: &ObjectId, user: &ObjectId, device: Device) -> Result<(), Error> {
+    let now = date::now();
+    let options = UpdateOptions::builder().upsert(true).build();
+    Session::update_one(
+        doc! { "_id": id, "user": user },
+        doc! {
+            "$set": {
+                "ip": device.ip.map(|ip| ip.to_string()),
+                "last_seen_at": now,
+                "expires_at": expires_at(now),
+                "updated_at": now
+            },
+            "$setOnInsert": {
+                "device_name": device.name,
+                "user_agent": device.user_agent,
+                "revoked_at": null,
+                "created_at": now
+            }
+        },
+        options,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Whether the session exists and was not revoked, recording that it is in
+/// use.
+pub async fn is_active(id: &ObjectId) -> Result<bool, Error> {
+    let session = match Session::find_by_id(id).await? {
+        Some(session) if session.revoked_at.is_none() => session,
+        _ => return Ok(false),
+    };
+
+    let now = date::now();
+    if now.timestamp_millis() - session.last_seen_at.timestamp_millis()
+        > LAST_SEEN_RESOLUTION * 1000
+    {
+        Session::update_one(
+            doc! { "_id": id },
+            doc! { "$set": { "last_seen_at": now } },
+            None,
+        )
+        .await?;
+    }
+
+    Ok(true)
+}
+
+/// Revokes a session of the given user along with its refresh tokens.
+/// Returns whether the session was found.
+pub async fn revoke(id: &ObjectId, user: &ObjectId) -> Result<bool, Error> {
+    let now = date::now();
+    let result = Session::update_one(
+        doc! { "_id": id, "user": user, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    if result.matched_count == 0 {
+        return Ok(false);
+    }
+
+    refresh_token::revoke_family(id).await?;
+
+    Ok(true)
+}
+
+/// Revokes every session of the user but the given one, along with their
+/// refresh tokens. All of them are revoked when no session is given.
+pub async fn revoke_others(user: &ObjectId, current: Option<&ObjectId>) -> Result<(), Error> {
+    let current = match current {
+        Some(current) => current,
+        None => {
+            revoke_all(user).await?;
+            return refresh_token::revoke_all(user).await;
+        }
+    };
+
+    let now = date::now();
+    Session::update_many(
+        doc! { "_id": { "$ne": current }, "user": user, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    refresh_token::revoke_all_except(user, current).await
+}
+
+pub async fn revoke_all(user: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    Session::update_many(
+        doc! { "user": user, "revoked_at": null },
+        doc! { "$set": { "revoked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::UpdateOptions;
+use wither::Model as WitherModel;
+
+use crate::errors::{Error, TooManyRequests};
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for SigninThrottle {}
+
+// Failed signins are counted per account and per IP. Once a counter reaches
+// its threshold every further attempt has to wait an exponentially growing
+// delay, and accounts get locked once they reach the lock threshold.
+// Counters are forgotten after the configured window without failures.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+    index(keys = r#"doc!{ "key": 1 }"#, options = r#"doc!{ "unique": true }"#),
+    index(
+        keys = r#"doc!{ "expires_at": 1 }"#,
+        options = r#"doc!{ "expireAfterSeconds": 0 }"#
+    )
+)]
+pub struct SigninThrottle {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key: String,
+    pub failures: u32,
+    pub last_failure_at: Date,
+    pub expires_at: Date,
+}
+
+/// Identifies the counters affected by a signin attempt.
+pub struct SigninKeys {
+    account: String,
+    ip: Option<String>,
+}
+
+impl SigninKeys {
+    pub fn new(email: &str, ip: Option<IpAddr>) -> Self {
+        Self {
+            account: account_key(email),
+            ip: ip.map(|ip| format!("ip:{ip}")),
+        }
+    }
+}
+
+fn account_key(email: &str) -> String {
+    format!("account:{}", email.to_lowercase())
+}
+
+fn backoff_delay(failures: u32, threshold: u32) -> i64 {
+    let settings = &SETTINGS.signin_throttle;
+    if failures < threshold {
+        return 0;
+    }
+
+    let exponent = (failures - threshold).min(20);
+    (settings.backoff_base_delay * 2_i64.pow(exponent)).min(settings.backoff_max_delay)
+}
+
+async fn retry_after(key: &str, threshold: u32) -> Result<i64, Error> {
+    let throttle = <SigninThrottle as ModelExt>::find_one(doc! { "key": key }, None).await?;
+    let throttle = match throttle {
+        Some(throttle) => throttle,
+        None => return Ok(0),
+    };
+
+    let delay = backoff_delay(throttle.failures, threshold);
+    let elapsed =
+        (date::now().timestamp_millis() - throttle.last_failure_at.timestamp_millis()) / 1000;
+
+    Ok((delay - elapsed).max(0))
+}
+
+/// Fails with `TooManyRequests` while the account or the IP is backing off.
+pub async fn check(keys: &SigninKeys) -> Result<(), Error> {
+    let settings = &SETTINGS.signin_throttle;
+    let mut wait = retry_after(&keys.account, settings.account_backoff_threshold).await?;
+
+    if let Some(ip) = &keys.ip {
+        wait = wait.max(retry_after(ip, settings.ip_backoff_threshold).await?);
+    }
+
+    if wait > 0 {
+        return Err(Error::TooManyRequests(TooManyRequests {
+            retry_after: wait,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Records a failed signin. Returns whether the account reached the lock
+/// threshold.
+pub async fn record_failure(keys: &SigninKeys) -> Result<bool, Error> {
+    let account_failures = increment(&keys.account).await?;
+
+    if let Some(ip) = &keys.ip {
+        increment(ip).await?;
+    }
+
+    Ok(account_failures >= SETTINGS.signin_throttle.lock_threshold)
+}
+
+/// Forgets the failed signins of an account.
+pub async fn clear(email: &str) -> Result<(), Error> {
+    <SigninThrottle as ModelExt>::delete_one(doc! { "key": account_key(email) }).await?;
+
+    Ok(())
+}
+
+async fn increment(key: &str) -> Result<u32, Error> {
+    let now = date::now();
+    let window = chrono::Duration::seconds(SETTINGS.signin_throttle.window);
+    let expires_at: Date = (now.to_chrono() + window).into();
+
+    SigninThrottle::update_one(
+        doc! { "key": key },
+        doc! {
+            "$inc": { "failures": 1 },
+            "$set": { "last_failure_at": now, "expires_at": expires_at }
+        },
+        UpdateOptions::builder().upsert(true).build(),
+    )
+    .await?;
+
+    let failures = <SigninThrottle as ModelExt>::find_one(doc! { "key": key }, None)
+        .await?
+        .map(|throttle| throttle.failures)
+        .unwrap_or_default();
+
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_backoff_below_the_threshold() {
+        assert_eq!(backoff_delay(0, 3), 0);
+        assert_eq!(backoff_delay(2, 3), 0);
+    }
+
+    #[test]
+    fn backoff_doubles_from_the_threshold() {
+        let base = SETTINGS.signin_throttle.backoff_base_delay;
+
+        assert_eq!(backoff_delay(3, 3), base);
+        assert_eq!(backoff_delay(4, 3), base * 2);
+        assert_eq!(backoff_delay(5, 3), base * 4);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let max = SETTINGS.signin_throttle.backoff_max_delay;
+
+        assert_eq!(backoff_delay(40, 3), max);
+        assert_eq!(backoff_delay(u32::MAX, 3), max);
+    }
+
+    #[test]
+    fn account_keys_ignore_the_email_case() {
+        let lower = SigninKeys::new("jane@example.com", None);
+        let mixed = SigninKeys::new("Jane@Example.COM", None);
+
+        assert_eq!(lower.account, mixed.account);
+    }
+
+    #[test]
+    fn ip_keys_are_only_set_with_an_ip() {
+        let ip = "203.0.113.7".parse().unwrap();
+
+        assert_eq!(
+            SigninKeys::new("jane@example.com", Some(ip)).ip.as_deref(),
+            Some("ip:203.0.113.7")
+        );
+        assert_eq!(SigninKeys::new("jane@example.com", None).ip, None);
+    }
+}
@@ -0,0 +1,132 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::models::checkin::Checkin;
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+
+impl ModelExt for Tag {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagKind {
+    /// Something the user did, like exercise or work.
+    Activity,
+    /// Any other context, like the weather or who they were with.
+    #[default]
+    Custom,
+}
+
+// The tags a user can put on their check-ins. Check-ins reference tags by id,
+// so renaming a tag doesn't touch them. Names are unique per user regardless
+// of their case.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(
+    keys = r#"doc!{ "user": 1, "key": 1 }"#,
+    options = r#"doc!{ "unique": true }"#
+))]
+pub struct Tag {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user: ObjectId,
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    /// The name used to tell duplicates apart.
+    pub key: String,
+    pub kind: TagKind,
+    pub updated_at: Date,
+    pub created_at: Date,
+}
+
+impl Tag {
+    pub fn new(user: ObjectId, name: String, kind: TagKind) -> Self {
+        let now = date::now();
+        let name = name.trim().to_string();
+        Self {
+            id: None,
+            user,
+            key: key(&name),
+            name,
+            kind,
+            updated_at: now,
+            created_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicTag {
+    #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub kind: TagKind,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub updated_at: Date,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: Date,
+}
+
+impl From<Tag> for PublicTag {
+    fn from(tag: Tag) -> Self {
+        Self {
+            id: tag.id.unwrap(),
+            name: tag.name,
+            kind: tag.kind,
+            updated_at: tag.updated_at,
+            created_at: tag.created_at,
+        }
+    }
+}
+
+pub fn key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Whether every tag exists and belongs to the user.
+pub async fn all_owned(user: &ObjectId, tags: &[ObjectId]) -> Result<bool, Error> {
+    if tags.is_empty() {
+        return Ok(true);
+    }
+
+    let count = Tag::count(doc! { "_id": { "$in": tags }, "user": user }).await?;
+    Ok(count == tags.len() as u64)
+}
+
+/// Moves the check-ins of the source tag to the target and removes the source.
+pub async fn merge(user: &ObjectId, source: &ObjectId, target: &ObjectId) -> Result<(), Error> {
+    let tagged = doc! { "user": user, "tags": source };
+    Checkin::update_many(
+        tagged.clone(),
+        doc! { "$addToSet": { "tags": target } },
+        None,
+    )
+    .await?;
+    Checkin::update_many(tagged, doc! { "$pull": { "tags": source } }, None).await?;
+
+    Tag::delete_one(doc! { "_id": source, "user": user }).await?;
+
+    Ok(())
+}
+
+/// Removes a tag along with its references from the check-ins.
+pub async fn remove(user: &ObjectId, tag: &ObjectId) -> Result<bool, Error> {
+    let result = Tag::delete_one(doc! { "_id": tag, "user": user }).await?;
+    if result.deleted_count == 0 {
+        return Ok(false);
+    }
+
+    Checkin::update_many(
+        doc! { "user": user, "tags": tag },
+        doc! { "$pull": { "tags": tag } },
+        None,
+    )
+    .await?;
+
+    Ok(true)
+}
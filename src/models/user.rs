@@ -1,20 +1,31 @@
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use tokio::task;
+use tracing::warn;
 use validator::Validate;
 use wither::bson::{doc, oid::ObjectId};
 use wither::Model as WitherModel;
 
 use crate::errors::Error;
+use crate::models::refresh_token;
+use crate::models::session;
+use crate::models::signin_throttle;
+use crate::models::signin_throttle::SigninKeys;
 use crate::utils::date;
 use crate::utils::date::Date;
 use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+use crate::utils::password;
+use crate::utils::totp;
 
 impl ModelExt for User {}
 
 #[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
-#[model(index(keys = r#"doc!{ "email": 1 }"#, options = r#"doc!{ "unique": true }"#))]
+#[model(
+    index(keys = r#"doc!{ "email": 1 }"#, options = r#"doc!{ "unique": true }"#),
+    index(keys = r#"doc!{ "identities.provider": 1, "identities.subject": 1 }"#)
+)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -24,10 +35,125 @@ pub struct User {
     pub last_name: String,
     #[validate(email)]
     pub email: String,
-    pub password: String,
+    /// Users who signed up with an identity provider may have no password.
+    pub password: Option<String>,
     pub updated_at: Date,
     pub created_at: Date,
     pub locked_at: Option<Date>,
+    pub email_verified_at: Option<Date>,
+    /// Email the user asked to change to, replacing `email` once confirmed.
+    pub pending_email: Option<String>,
+    /// When the account gets erased, unless the deletion is cancelled.
+    pub deletion_scheduled_at: Option<Date>,
+    /// When the erasure of the account started, it can't be cancelled after.
+    pub erasing_at: Option<Date>,
+    /// Tokens issued before this date are rejected, see `is_token_stale`.
+    pub tokens_valid_after: Option<Date>,
+    pub two_factor: Option<TwoFactor>,
+    /// Accounts at OpenID Connect providers linked to the user.
+    #[serde(default)]
+    pub identities: Vec<Identity>,
+    /// Roles granting access to staff tooling, regular users have none.
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub preferences: Preferences,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactor {
+    /// Base32 encoded TOTP secret.
+    pub secret: String,
+    /// Set once the enrollment is confirmed with a valid code, two-factor
+    /// authentication is only required from then on.
+    pub enabled_at: Option<Date>,
+    /// Hashes of the recovery codes that were not used yet.
+    pub recovery_codes: Vec<String>,
+    /// TOTP time step of the last accepted code, codes of this step or an
+    /// earlier one are rejected so they can't be replayed.
+    #[serde(default)]
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Saturday,
+    Sunday,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+// Preferences are updated field by field, so users who never had them may
+// have a partial document. Missing fields take their default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// IANA time zone, the days of the user's check-ins are the days in it.
+    pub timezone: String,
+    /// BCP 47 language tag.
+    pub locale: String,
+    pub week_start: WeekStart,
+    /// Local times of the day, formatted as `HH:MM`, at which the user wants
+    /// to be reminded to check in.
+    pub reminder_times: Vec<String>,
+    pub units: Units,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            week_start: WeekStart::default(),
+            reminder_times: vec![],
+            units: Units::default(),
+        }
+    }
+}
+
+impl Preferences {
+    /// The time zone of the user, UTC if the stored one is unknown.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    /// Name of the provider in the `auth.oidc_providers` setting.
+    pub provider: String,
+    /// The `sub` claim of the provider's ID tokens.
+    pub subject: String,
+    pub linked_at: Date,
+}
+
+impl Identity {
+    pub fn new<A, B>(provider: A, subject: B) -> Self
+    where
+        A: Into<String>,
+        B: Into<String>,
+    {
+        Self {
+            provider: provider.into(),
+            subject: subject.into(),
+            linked_at: date::now(),
+        }
+    }
 }
 
 impl User {
@@ -44,15 +170,74 @@ impl User {
             first_name: first_name.into(),
             last_name: last_name.into(),
             email: email.into(),
-            password: password_hash.into(),
+            password: Some(password_hash.into()),
             updated_at: now,
             created_at: now,
             locked_at: None,
+            email_verified_at: None,
+            pending_email: None,
+            deletion_scheduled_at: None,
+            erasing_at: None,
+            tokens_valid_after: None,
+            two_factor: None,
+            identities: vec![],
+            roles: vec![],
+            preferences: Preferences::default(),
         }
     }
 
-    pub fn is_password_match(&self, password: &str) -> bool {
-        bcrypt::verify(password, self.password.as_ref()).unwrap_or(false)
+    /// Creates a user without password, who signs in with the given identity.
+    /// The email must have been verified by the identity provider.
+    pub fn new_with_identity<A, B, C>(
+        first_name: A,
+        last_name: B,
+        email: C,
+        identity: Identity,
+    ) -> Self
+    where
+        A: Into<String>,
+        B: Into<String>,
+        C: Into<String>,
+    {
+        let now = date::now();
+        Self {
+            id: None,
+            first_name: first_name.into(),
+            last_name: last_name.into(),
+            email: email.into(),
+            password: None,
+            updated_at: now,
+            created_at: now,
+            locked_at: None,
+            email_verified_at: Some(now),
+            pending_email: None,
+            deletion_scheduled_at: None,
+            erasing_at: None,
+            tokens_valid_after: None,
+            two_factor: None,
+            identities: vec![identity],
+            roles: vec![],
+            preferences: Preferences::default(),
+        }
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    pub fn is_two_factor_enabled(&self) -> bool {
+        self.two_factor
+            .as_ref()
+            .is_some_and(|two_factor| two_factor.enabled_at.is_some())
+    }
+
+    /// Whether a token issued at the given UTC timestamp (in seconds) was
+    /// invalidated by a later call to `invalidate_tokens`.
+    pub fn is_token_stale(&self, issued_at: usize) -> bool {
+        match self.tokens_valid_after {
+            Some(valid_after) => (issued_at as i64) < valid_after.timestamp_millis() / 1000,
+            None => false,
+        }
     }
 }
 
@@ -63,6 +248,7 @@ pub struct PublicUser {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    pub roles: Vec<Role>,
     #[serde(with = "bson_datetime_as_rfc3339_string")]
     pub updated_at: Date,
     #[serde(with = "bson_datetime_as_rfc3339_string")]
@@ -76,24 +262,122 @@ impl From<User> for PublicUser {
             first_name: user.first_name.clone(),
             last_name: user.last_name.clone(),
             email: user.email.clone(),
+            roles: user.roles,
             updated_at: user.updated_at,
             created_at: user.created_at,
         }
     }
 }
 
+/// The time zone of the user, dates are bucketed in it.
+pub async fn timezone(user: &ObjectId) -> Result<Tz, Error> {
+    let user = User::find_by_id(user).await?.ok_or_else(Error::not_found)?;
+
+    Ok(user.preferences.tz())
+}
+
+/// Invalidates every access token issued to the user so far, along with all
+/// of their refresh tokens and sessions.
+pub async fn invalidate_tokens(user: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    User::update_one(
+        doc! { "_id": user },
+        doc! { "$set": { "tokens_valid_after": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    session::revoke_all(user).await?;
+    refresh_token::revoke_all(user).await
+}
+
+/// Locks the user out, signins are rejected until the user is unlocked.
+pub async fn lock(user: &ObjectId) -> Result<(), Error> {
+    let now = date::now();
+    User::update_one(
+        doc! { "_id": user, "locked_at": null },
+        doc! { "$set": { "locked_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed signin of the user, locking them once they reach the
+/// configured threshold. Returns whether the user got locked.
+pub async fn record_failed_signin(user: &User, keys: &SigninKeys) -> Result<bool, Error> {
+    if !signin_throttle::record_failure(keys).await? {
+        return Ok(false);
+    }
+
+    warn!("Too many failed signins, locking user {}", user.id.unwrap());
+    lock(&user.id.unwrap()).await?;
+
+    Ok(true)
+}
+
+/// Unlocks the user and forgets their failed signins.
+pub async fn unlock(user: &User) -> Result<(), Error> {
+    User::update_one(
+        doc! { "_id": user.id },
+        doc! { "$set": { "locked_at": null, "updated_at": date::now() } },
+        None,
+    )
+    .await?;
+
+    signin_throttle::clear(&user.email).await
+}
+
+/// Checks a second factor code, either a TOTP code or one of the user's
+/// recovery codes. Recovery codes can only be used once.
+pub async fn verify_second_factor(user: &User, code: &str) -> Result<bool, Error> {
+    let two_factor = match &user.two_factor {
+        Some(two_factor) => two_factor,
+        None => return Ok(false),
+    };
+
+    if let Some(step) = totp::verify(&two_factor.secret, &user.email, code)? {
+        // The step is only recorded if no code of the same step or a later one
+        // was accepted in the meantime, concurrent requests can't both pass
+        let result = User::update_one(
+            doc! {
+                "_id": user.id,
+                "two_factor.last_used_step": { "$not": { "$gte": step } }
+            },
+            doc! { "$set": { "two_factor.last_used_step": step } },
+            None,
+        )
+        .await?;
+
+        return Ok(result.modified_count == 1);
+    }
+
+    let recovery_code = opaque_token::hash(totp::normalize_recovery_code(code));
+    let result = User::update_one(
+        doc! { "_id": user.id, "two_factor.recovery_codes": &recovery_code },
+        doc! { "$pull": { "two_factor.recovery_codes": &recovery_code } },
+        None,
+    )
+    .await?;
+
+    Ok(result.modified_count == 1)
+}
+
+/// Verifies a password against the user's hash. Users without password never
+/// match.
+pub async fn check_password(user: &User, password: String) -> Result<bool, Error> {
+    match user.password.clone() {
+        Some(hash) => verify_password(password, hash).await,
+        None => Ok(false),
+    }
+}
+
 pub async fn hash_password<P>(password: P) -> Result<String, Error>
 where
     P: AsRef<str> + Send + 'static,
 {
-    #[cfg(not(test))]
-    let cost = bcrypt::DEFAULT_COST;
-    #[cfg(test)]
-    let cost = 4;
-    task::spawn_blocking(move || bcrypt::hash(password.as_ref(), cost))
-        .await
-        .map_err(Error::RunSyncTask)?
-        .map_err(Error::HashPassword)
+    password::hash(password).await
 }
 
 pub async fn verify_password<P, H>(password: P, hash: H) -> Result<bool, Error>
@@ -101,8 +385,27 @@ where
     P: AsRef<str> + Send + 'static,
     H: AsRef<str> + Send + 'static,
 {
-    task::spawn_blocking(move || bcrypt::verify(password.as_ref(), hash.as_ref()))
-        .await
-        .map_err(Error::RunSyncTask)?
-        .map_err(|_| Error::InvalidPassword("Invalid password".to_string()))
-}
\ No newline at end of file
+    password::verify(password, hash).await
+}
+
+/// Replaces the password hash of the user when it was made with an outdated
+/// algorithm or cost. Only done right after the password was verified, as
+/// that's the only time the plain password is known.
+pub async fn upgrade_password_hash(user: &User, plain_password: String) -> Result<(), Error> {
+    let current_hash = match &user.password {
+        Some(hash) if password::needs_rehash(hash) => hash,
+        _ => return Ok(()),
+    };
+
+    let password_hash = hash_password(plain_password).await?;
+    // Matching the current hash avoids overwriting a password changed in the
+    // meantime
+    User::update_one(
+        doc! { "_id": user.id, "password": current_hash },
+        doc! { "$set": { "password": password_hash } },
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use axum::{
+    extract::{Path, Query},
+    routing::{get, post},
+    Json, Router,
+};
+use bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+use wither::mongodb::options::FindOptions;
+
+use crate::errors::Error;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::checkin::Checkin;
+use crate::models::user;
+use crate::models::user::{Role, User};
+use crate::services::audit;
+use crate::utils::authenticate_request::{Admin, RequireRole};
+use crate::utils::custom_response::CustomResponseResult as Response;
+use crate::utils::custom_response::{CustomResponseBuilder, ResponsePagination};
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::models::ModelExt;
+use crate::utils::pagination::Pagination;
+use crate::utils::request_context::RequestContext;
+use crate::utils::to_object_id::to_object_id;
+
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/admin/users", get(query_users))
+        .route("/api/admin/users/:id", get(get_user_by_id))
+        .route("/api/admin/users/:id/lock", post(lock_user))
+        .route("/api/admin/users/:id/unlock", post(unlock_user))
+        .route("/api/admin/stats/checkins", get(get_checkin_stats))
+        .route("/api/admin/audit-events", get(query_audit_events))
+}
+
+/// A user as seen by staff, including the state of their account.
+#[derive(Debug, Serialize)]
+pub struct AdminUser {
+    id: String,
+    first_name: String,
+    last_name: String,
+    email: String,
+    roles: Vec<Role>,
+    email_verified: bool,
+    two_factor_enabled: bool,
+    locked_at: Option<String>,
+    identity_providers: Vec<String>,
+    updated_at: String,
+    created_at: String,
+}
+
+impl From<User> for AdminUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id.unwrap().to_hex(),
+            email_verified: user.is_email_verified(),
+            two_factor_enabled: user.is_two_factor_enabled(),
+            locked_at: user.locked_at.map(|date| date.to_chrono().to_rfc3339()),
+            identity_providers: user
+                .identities
+                .into_iter()
+                .map(|identity| identity.provider)
+                .collect(),
+            first_name: user.first_name,
+            last_name: user.last_name,
+            email: user.email,
+            roles: user.roles,
+            updated_at: user.updated_at.to_chrono().to_rfc3339(),
+            created_at: user.created_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserQueryParams {
+    /// Matched against the email and names of the users, case insensitive.
+    q: Option<String>,
+    locked: Option<bool>,
+}
+
+async fn query_users(
+    RequireRole(_, _): RequireRole<Admin>,
+    Query(params): Query<UserQueryParams>,
+    pagination: Pagination,
+) -> Response<Vec<AdminUser>> {
+    let mut query = doc! {};
+
+    if let Some(q) = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = doc! { "$regex": escape_regex(q), "$options": "i" };
+        query.insert(
+            "$or",
+            vec![
+                doc! { "email": pattern.clone() },
+                doc! { "first_name": pattern.clone() },
+                doc! { "last_name": pattern },
+            ],
+        );
+    }
+
+    if let Some(locked) = params.locked {
+        let operator = if locked { "$ne" } else { "$eq" };
+        query.insert("locked_at", doc! { operator: null });
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1_i32 })
+        .skip(pagination.offset)
+        .limit(pagination.limit as i64)
+        .build();
+
+    let (users, count) = User::find_and_count(query, options).await?;
+    let users = users
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<AdminUser>>();
+
+    let res = CustomResponseBuilder::new()
+        .body(users)
+        .pagination(ResponsePagination {
+            count,
+            offset: pagination.offset,
+            limit: pagination.limit,
+        })
+        .build();
+
+    debug!("Returning users");
+    Ok(res)
+}
+
+async fn get_user_by_id(
+    RequireRole(_, _): RequireRole<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUser>, Error> {
+    let user_id = to_object_id(id)?;
+    let user = User::find_by_id(&user_id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    debug!("Returning user");
+    Ok(Json(AdminUser::from(user)))
+}
+
+async fn lock_user(
+    RequireRole(admin, _): RequireRole<Admin>,
+    ctx: RequestContext,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUser>, Error> {
+    let user_id = to_object_id(id)?;
+    if user_id == admin.id {
+        return Err(Error::bad_request_with_message(
+            "You can't lock yourself".to_string(),
+        ));
+    }
+
+    if !User::exists(doc! { "_id": &user_id }).await? {
+        return Err(Error::not_found());
+    }
+
+    // Locked users must not keep using the tokens they already have
+    user::lock(&user_id).await?;
+    user::invalidate_tokens(&user_id).await?;
+    info!("User {} locked by admin {}", user_id, admin.id);
+
+    let event = AuditEvent::new(AuditEventKind::UserLocked)
+        .actor(admin.id)
+        .subject(user_id)
+        .details(doc! { "reason": "admin" });
+    audit::emit(&ctx, event).await;
+
+    let user = User::find_by_id(&user_id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(AdminUser::from(user)))
+}
+
+async fn unlock_user(
+    RequireRole(admin, _): RequireRole<Admin>,
+    ctx: RequestContext,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUser>, Error> {
+    let user_id = to_object_id(id)?;
+    let user = User::find_by_id(&user_id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    user::unlock(&user).await?;
+    info!("User {} unlocked by admin {}", user_id, admin.id);
+
+    let event = AuditEvent::new(AuditEventKind::UserUnlocked)
+        .actor(admin.id)
+        .subject(user_id);
+    audit::emit(&ctx, event).await;
+
+    let user = User::find_by_id(&user_id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(AdminUser::from(user)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckinStats {
+    users: UserCounts,
+    checkins: CheckinCounts,
+    /// Means over every check-in, absent when there are none.
+    averages: Option<CheckinAverages>,
+    emotions: Vec<EmotionCount>,
+    last_7_days: ActivityCount,
+    last_30_days: ActivityCount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserCounts {
+    total: u64,
+    verified: u64,
+    locked: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckinCounts {
+    total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckinAverages {
+    mood_rating: f64,
+    intensity: f64,
+    energy_level: f64,
+    stress_level: f64,
+    wellbeing: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmotionCount {
+    #[serde(rename(deserialize = "_id"))]
+    emotion: String,
+    count: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ActivityCount {
+    checkins: u64,
+    active_users: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CheckinFacets {
+    averages: Vec<CheckinAverages>,
+    emotions: Vec<EmotionCount>,
+    last_7_days: Vec<ActivityCount>,
+    last_30_days: Vec<ActivityCount>,
+}
+
+async fn get_checkin_stats(
+    RequireRole(_, _): RequireRole<Admin>,
+) -> Result<Json<CheckinStats>, Error> {
+    let now = date::now().to_chrono();
+    let last_7_days = Date::from_chrono(now - chrono::Duration::days(7));
+    let last_30_days = Date::from_chrono(now - chrono::Duration::days(30));
+
+    let pipeline = vec![doc! {
+        "$facet": {
+            "averages": [{
+                "$group": {
+                    "_id": null,
+                    "mood_rating": { "$avg": "$mood_rating" },
+                    "intensity": { "$avg": "$intensity" },
+                    "energy_level": { "$avg": "$energy_level" },
+                    "stress_level": { "$avg": "$stress_level" },
+                    "wellbeing": { "$avg": "$wellbeing" },
+                }
+            }],
+            "emotions": [
+                { "$group": { "_id": "$primary_emotion", "count": { "$sum": 1 } } },
+                { "$sort": { "count": -1 } },
+            ],
+            "last_7_days": activity_since(last_7_days),
+            "last_30_days": activity_since(last_30_days),
+        }
+    }];
+
+    let facets = Checkin::aggregate::<CheckinFacets>(pipeline)
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let stats = CheckinStats {
+        users: UserCounts {
+            total: User::count(doc! {}).await?,
+            verified: User::count(doc! { "email_verified_at": { "$ne": null } }).await?,
+            locked: User::count(doc! { "locked_at": { "$ne": null } }).await?,
+        },
+        checkins: CheckinCounts {
+            total: facets.emotions.iter().map(|emotion| emotion.count).sum(),
+        },
+        averages: facets.averages.into_iter().next(),
+        emotions: facets.emotions,
+        last_7_days: facets.last_7_days.into_iter().next().unwrap_or_default(),
+        last_30_days: facets.last_30_days.into_iter().next().unwrap_or_default(),
+    };
+
+    debug!("Returning check-in stats");
+    Ok(Json(stats))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminAuditEvent {
+    id: String,
+    kind: AuditEventKind,
+    actor: Option<String>,
+    subject: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    details: Option<Document>,
+    created_at: String,
+}
+
+impl From<AuditEvent> for AdminAuditEvent {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            id: event.id.unwrap().to_hex(),
+            kind: event.kind,
+            actor: event.actor.map(|actor| actor.to_hex()),
+            subject: event.subject.map(|subject| subject.to_hex()),
+            ip: event.ip,
+            user_agent: event.user_agent,
+            request_id: event.request_id,
+            details: event.details,
+            created_at: event.created_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventQueryParams {
+    actor: Option<String>,
+    subject: Option<String>,
+    /// Comma separated list of kinds.
+    kind: Option<String>,
+    ip: Option<String>,
+    /// Matched against the user agent, case insensitive.
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    /// RFC 3339 date, inclusive.
+    from: Option<String>,
+    /// RFC 3339 date, exclusive.
+    to: Option<String>,
+}
+
+async fn query_audit_events(
+    RequireRole(_, _): RequireRole<Admin>,
+    Query(params): Query<AuditEventQueryParams>,
+    pagination: Pagination,
+) -> Response<Vec<AdminAuditEvent>> {
+    let mut query = doc! {};
+
+    if let Some(actor) = params.actor {
+        query.insert("actor", to_object_id(actor)?);
+    }
+    if let Some(subject) = params.subject {
+        query.insert("subject", to_object_id(subject)?);
+    }
+
+    if let Some(kind) = params.kind.as_deref() {
+        let kinds = kind.split(',').map(str::trim).collect::<Vec<&str>>();
+        if let Some(unknown) = kinds.iter().find(|kind| !is_audit_event_kind(kind)) {
+            return Err(Error::bad_request_with_message(format!(
+                "Unknown event kind {}",
+                unknown
+            )));
+        }
+        query.insert("kind", doc! { "$in": kinds });
+    }
+
+    if let Some(ip) = params.ip {
+        query.insert("ip", ip);
+    }
+    if let Some(user_agent) = params.user_agent.as_deref().filter(|ua| !ua.is_empty()) {
+        query.insert(
+            "user_agent",
+            doc! { "$regex": escape_regex(user_agent), "$options": "i" },
+        );
+    }
+    if let Some(request_id) = params.request_id {
+        query.insert("request_id", request_id);
+    }
+
+    let mut created_at = doc! {};
+    if let Some(from) = params.from.as_deref() {
+        created_at.insert("$gte", parse_date("from", from)?);
+    }
+    if let Some(to) = params.to.as_deref() {
+        created_at.insert("$lt", parse_date("to", to)?);
+    }
+    if !created_at.is_empty() {
+        query.insert("created_at", created_at);
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1_i32 })
+        .skip(pagination.offset)
+        .limit(pagination.limit as i64)
+        .build();
+
+    let (events, count) = AuditEvent::find_and_count(query, options).await?;
+    let events = events
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<AdminAuditEvent>>();
+
+    let res = CustomResponseBuilder::new()
+        .body(events)
+        .pagination(ResponsePagination {
+            count,
+            offset: pagination.offset,
+            limit: pagination.limit,
+        })
+        .build();
+
+    debug!("Returning audit events");
+    Ok(res)
+}
+
+fn is_audit_event_kind(kind: &str) -> bool {
+    serde_json::from_value::<AuditEventKind>(serde_json::Value::from(kind)).is_ok()
+}
+
+fn parse_date(name: &str, value: &str) -> Result<Date, Error> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|date| Date::from_chrono(date.with_timezone(&chrono::Utc)))
+        .map_err(|_| Error::bad_request_with_message(format!("Invalid {} date", name)))
+}
+
+/// Pipeline counting the check-ins created since the given date, and the
+/// number of distinct users who created them.
+fn activity_since(since: Date) -> Vec<Document> {
+    vec![
+        doc! { "$match": { "created_at": { "$gte": since } } },
+        doc! { "$group": { "_id": "$user", "checkins": { "$sum": 1 } } },
+        doc! {
+            "$group": {
+                "_id": null,
+                "checkins": { "$sum": "$checkins" },
+                "active_users": { "$sum": 1 },
+            }
+        },
+    ]
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
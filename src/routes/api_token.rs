@@ -0,0 +1,125 @@
+use axum::http::StatusCode;
+use axum::{
+    extract::Path,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use bson::doc;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use validator::Validate;
+use wither::mongodb::options::FindOptions;
+
+use crate::errors::Error;
+use crate::models::api_token;
+use crate::models::api_token::{ApiToken, PublicApiToken, Scope};
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::services::audit;
+use crate::utils::custom_response::CustomResponseResult as Response;
+use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::request_context::RequestContext;
+use crate::utils::to_object_id::to_object_id;
+use crate::utils::token::Claims;
+
+// Personal access tokens can only be managed with access tokens obtained by
+// signing in, a personal access token can't create or revoke tokens.
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/auth/tokens", post(create_api_token))
+        .route("/api/auth/tokens", get(query_api_tokens))
+        .route("/api/auth/tokens/:id", delete(revoke_api_token))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateApiToken {
+    name: String,
+    scopes: Vec<Scope>,
+    /// Tokens created without expiration are valid until revoked.
+    expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedApiToken {
+    /// The raw token, only returned once.
+    token: String,
+    #[serde(flatten)]
+    api_token: PublicApiToken,
+}
+
+async fn create_api_token(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+    Json(payload): Json<CreateApiToken>,
+) -> Response<CreatedApiToken> {
+    let expires_at = payload.expires_in_days.map(|days| {
+        let expires_at = date::now().to_chrono() + chrono::Duration::days(days.into());
+        expires_at.into()
+    });
+
+    let token = api_token::generate();
+    let api_token = ApiToken::new(user.id, payload.name, payload.scopes, &token, expires_at);
+    api_token
+        .validate()
+        .map_err(|e| Error::bad_request_with_message(format!("Validation error: {:?}", e)))?;
+
+    let api_token = ApiToken::create(api_token).await?;
+    let event = AuditEvent::for_user(AuditEventKind::ApiTokenCreated, user.id)
+        .details(doc! { "api_token": api_token.id, "name": &api_token.name });
+    audit::emit(&ctx, event).await;
+
+    let res = CreatedApiToken {
+        token,
+        api_token: PublicApiToken::from(api_token),
+    };
+
+    let res = CustomResponseBuilder::new()
+        .body(res)
+        .status_code(StatusCode::CREATED)
+        .build();
+
+    Ok(res)
+}
+
+async fn query_api_tokens(Claims { user, .. }: Claims) -> Response<Vec<PublicApiToken>> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1_i32 })
+        .build();
+
+    let api_tokens = ApiToken::find(doc! { "user": &user.id }, options).await?;
+    let api_tokens = api_tokens
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<PublicApiToken>>();
+
+    let res = CustomResponseBuilder::new().body(api_tokens).build();
+
+    debug!("Returning API tokens");
+    Ok(res)
+}
+
+async fn revoke_api_token(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+    Path(id): Path<String>,
+) -> Result<CustomResponse<()>, Error> {
+    let api_token_id = to_object_id(id)?;
+    let delete_result =
+        ApiToken::delete_one(doc! { "_id": api_token_id, "user": &user.id }).await?;
+
+    if delete_result.deleted_count == 0 {
+        debug!("API token not found, returning 404 status code");
+        return Err(Error::not_found());
+    }
+
+    let event = AuditEvent::for_user(AuditEventKind::ApiTokenRevoked, user.id)
+        .details(doc! { "api_token": api_token_id });
+    audit::emit(&ctx, event).await;
+
+    let res = CustomResponseBuilder::new()
+        .status_code(StatusCode::NO_CONTENT)
+        .build();
+
+    Ok(res)
+}
@@ -1,26 +1,65 @@
-use axum::{routing::post, Json, Router};
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
 use bson::doc;
 use serde::{Deserialize, Serialize};
+use tracing::error;
 use validator::Validate;
 
-use crate::errors::Error;
+use crate::errors::{AuthenticateError, Error};
+use crate::mailer;
+use crate::mailer::Email;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::oidc_state::OidcState;
+use crate::models::one_time_token;
+use crate::models::one_time_token::TokenPurpose;
+use crate::models::refresh_token;
+use crate::models::revoked_token;
+use crate::models::session;
+use crate::models::session::Device;
 use crate::models::user;
 use crate::models::user::{PublicUser, User};
+use crate::services::account;
+use crate::services::account::NewAccount;
+use crate::services::audit;
 use crate::settings::SETTINGS;
+use crate::utils::client_ip::ClientIp;
+use crate::utils::date;
 use crate::utils::models::ModelExt;
+use crate::utils::oidc;
+use crate::utils::opaque_token;
+use crate::utils::password_policy;
+use crate::utils::request_context::RequestContext;
 use crate::utils::token;
+use crate::utils::token::Claims;
+use crate::utils::user_agent::UserAgent;
 
 pub fn create_route() -> Router {
     Router::new()
         .route("/api/auth/signup", post(signup))
         .route("/api/auth/signin", post(signin))
+        .route("/api/auth/signin/mfa", post(signin_mfa))
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/logout-all", post(logout_all))
+        .route("/api/auth/forgot-password", post(forgot_password))
+        .route("/api/auth/reset-password", post(reset_password))
+        .route("/api/auth/verify-email", post(verify_email))
+        .route(
+            "/api/auth/verify-email/resend",
+            post(resend_verification_email),
+        )
+        .route("/api/auth/verify-email-change", post(verify_email_change))
+        .route("/api/auth/oidc/:provider/authorize", get(oidc_authorize))
+        .route("/api/auth/oidc/:provider/callback", post(oidc_callback))
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct SignupRequest {
     #[validate(email)]
     email: String,
-    #[validate(length(min = 8))]
     password: String,
     #[validate(length(min = 1))]
     #[serde(rename = "firstName")]
@@ -28,6 +67,8 @@ pub struct SignupRequest {
     #[validate(length(min = 1))]
     #[serde(rename = "lastName")]
     last_name: String,
+    #[serde(rename = "deviceName")]
+    device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -41,7 +82,13 @@ pub struct SignupResponseData {
     last_name: String,
     #[serde(rename = "createdAt")]
     created_at: String,
+    #[serde(rename = "emailVerified")]
+    email_verified: bool,
     token: String,
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: i64,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,19 +98,13 @@ pub struct SignupResponse {
     data: SignupResponseData,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    success: bool,
-    message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
-}
-
 #[derive(Debug, Deserialize, Validate)]
 pub struct SigninRequest {
     #[validate(email)]
     email: String,
     password: String,
+    #[serde(rename = "deviceName")]
+    device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -75,47 +116,141 @@ pub struct SigninResponseData {
     first_name: String,
     #[serde(rename = "lastName")]
     last_name: String,
+    #[serde(rename = "emailVerified")]
+    email_verified: bool,
     token: String,
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaRequiredResponseData {
+    #[serde(rename = "mfaRequired")]
+    mfa_required: bool,
+    #[serde(rename = "mfaToken")]
+    mfa_token: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SigninData {
+    Authenticated(SigninResponseData),
+    MfaRequired(MfaRequiredResponseData),
 }
 
 #[derive(Debug, Serialize)]
 pub struct SigninResponse {
     success: bool,
     message: String,
-    data: SigninResponseData,
+    data: SigninData,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SigninMfaRequest {
+    #[serde(rename = "mfaToken")]
+    mfa_token: String,
+    code: String,
+    #[serde(rename = "deviceName")]
+    device_name: Option<String>,
+}
 
-async fn signup(Json(payload): Json<SignupRequest>) -> Result<Json<SignupResponse>, Error> {
-    // Check if user with email already exists
-    let existing_user = User::find_one(doc! { "email": &payload.email }, None).await?;
-    if existing_user.is_some() {
-        return Err(Error::bad_request_with_message("Email already registered".to_string()));
-    }
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshResponseData {
+    token: String,
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshResponse {
+    success: bool,
+    message: String,
+    data: RefreshResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    #[serde(rename = "refreshToken")]
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email)]
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    token: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OidcAuthorizeResponseData {
+    #[serde(rename = "authorizationUrl")]
+    authorization_url: String,
+    state: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OidcAuthorizeResponse {
+    success: bool,
+    message: String,
+    data: OidcAuthorizeResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackRequest {
+    code: String,
+    state: String,
+    #[serde(rename = "deviceName")]
+    device_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    success: bool,
+    message: String,
+}
+
+async fn signup(
+    ctx: RequestContext,
+    Json(payload): Json<SignupRequest>,
+) -> Result<Json<SignupResponse>, Error> {
+    let new_account = NewAccount {
+        first_name: payload.first_name,
+        last_name: payload.last_name,
+        email: payload.email,
+        password: payload.password,
+    };
+    let user = account::signup(new_account, &ctx).await?;
+
+    // Start a session and generate JWT and refresh tokens
+    let device = Device::new(payload.device_name, ctx.user_agent, ctx.ip);
+    let tokens = account::start_session(&user, device).await?;
+    let public_user = PublicUser::from(user);
 
-    // Hash the password
-    let password = payload.password.clone(); // Clone the password to extend its lifetime
-    let password_hash = user::hash_password(password).await?;
-    
-    // Create new user
-    let user = User::new(
-        payload.first_name,
-        payload.last_name,
-        payload.email,
-        password_hash,
-    );
-    
-    // Save user to database
-    let user = User::create(user).await?;
-    let public_user = PublicUser::from(user.clone());
-    
-    // Generate JWT token
-    let secret = SETTINGS.auth.secret.as_str();
-    let token = token::create(user, secret)?;
-    
     // Format created_at date - convert it to rfc3339 string format
     let created_at = public_user.created_at.to_chrono().to_rfc3339();
-    
+
     // Prepare response
     let response = SignupResponse {
         success: true,
@@ -126,43 +261,366 @@ async fn signup(Json(payload): Json<SignupRequest>) -> Result<Json<SignupRespons
             first_name: public_user.first_name,
             last_name: public_user.last_name,
             created_at,
-            token,
+            email_verified: false,
+            token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            expires_in: SETTINGS.auth.access_token_ttl,
         },
     };
-    
+
     Ok(Json(response))
 }
 
+async fn signin(
+    ctx: RequestContext,
+    Json(payload): Json<SigninRequest>,
+) -> Result<Json<SigninResponse>, Error> {
+    let user = account::authenticate(&payload.email, payload.password, &ctx).await?;
 
-async fn signin(Json(payload): Json<SigninRequest>) -> Result<Json<SigninResponse>, Error> {
-    // Find user by email
-    let user = User::find_one(doc! { "email": &payload.email }, None).await?
-        .ok_or_else(|| Error::unauthorized_with_message("Invalid email or password".to_string()))?;
+    let device = Device::new(payload.device_name, ctx.user_agent, ctx.ip);
+    complete_signin(user, device).await
+}
+
+async fn complete_signin(user: User, device: Device) -> Result<Json<SigninResponse>, Error> {
+    // Users with two-factor authentication enabled get a short lived token
+    // instead, which is exchanged for the real ones at /api/auth/signin/mfa
+    if user.is_two_factor_enabled() {
+        let mfa_token = token::create_mfa_token(user.id.unwrap())?;
 
-    // Verify password
-    let is_valid = user::verify_password(payload.password, user.password.clone()).await?;
-    if !is_valid {
-        return Err(Error::unauthorized_with_message("Invalid email or password".to_string()));
+        return Ok(Json(SigninResponse {
+            success: true,
+            message: "Two-factor authentication required".to_string(),
+            data: SigninData::MfaRequired(MfaRequiredResponseData {
+                mfa_required: true,
+                mfa_token,
+                expires_in: SETTINGS.auth.mfa_token_ttl,
+            }),
+        }));
     }
-    
-    // Generate JWT token
-    let secret = SETTINGS.auth.secret.as_str();
-    let token = token::create(user.clone(), secret)?;
-    
+
+    signin_response(user, device).await
+}
+
+async fn signin_mfa(
+    ctx: RequestContext,
+    Json(payload): Json<SigninMfaRequest>,
+) -> Result<Json<SigninResponse>, Error> {
+    let user = account::authenticate_second_factor(&payload.mfa_token, &payload.code, &ctx).await?;
+
+    let device = Device::new(payload.device_name, ctx.user_agent, ctx.ip);
+    signin_response(user, device).await
+}
+
+async fn signin_response(user: User, device: Device) -> Result<Json<SigninResponse>, Error> {
+    // Start a session and generate JWT and refresh tokens
+    let tokens = account::start_session(&user, device).await?;
+    let email_verified = user.is_email_verified();
     let public_user = PublicUser::from(user);
-    
+
     // Prepare response
     let response = SigninResponse {
         success: true,
         message: "User signed in successfully".to_string(),
-        data: SigninResponseData {
+        data: SigninData::Authenticated(SigninResponseData {
             user_id: public_user.id.to_hex(),
             email: public_user.email,
             first_name: public_user.first_name,
             last_name: public_user.last_name,
+            email_verified,
+            token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            expires_in: SETTINGS.auth.access_token_ttl,
+        }),
+    };
+
+    Ok(Json(response))
+}
+
+async fn refresh(
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Json<RefreshResponse>, Error> {
+    // Rotate the refresh token, this fails if it was already used
+    let (user_id, session, refresh_token) = refresh_token::rotate(&payload.refresh_token).await?;
+
+    let user = User::find_by_id(&user_id)
+        .await?
+        .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+    if user.locked_at.is_some() {
+        return Err(Error::Authenticate(AuthenticateError::Locked));
+    }
+
+    session::extend(&session, &user_id, Device::new(None, user_agent, ip)).await?;
+    let token = token::create(user, session)?;
+
+    let response = RefreshResponse {
+        success: true,
+        message: "Token refreshed successfully".to_string(),
+        data: RefreshResponseData {
             token,
+            refresh_token,
+            expires_in: SETTINGS.auth.access_token_ttl,
         },
     };
-    
+
     Ok(Json(response))
-}
\ No newline at end of file
+}
+
+async fn logout(
+    claims: Claims,
+    ctx: RequestContext,
+    payload: Option<Json<LogoutRequest>>,
+) -> Result<Json<MessageResponse>, Error> {
+    // Revoke the access token used for this request, and its session
+    revoked_token::revoke(&claims.jti, claims.exp).await?;
+    if let Some(sid) = &claims.sid {
+        session::revoke(sid, &claims.user.id).await?;
+
+        let event = AuditEvent::for_user(AuditEventKind::SessionRevoked, claims.user.id)
+            .details(doc! { "session": sid, "reason": "logout" });
+        audit::emit(&ctx, event).await;
+    }
+
+    // Revoke the refresh token too, when provided
+    if let Some(refresh_token) = payload.and_then(|Json(payload)| payload.refresh_token) {
+        refresh_token::revoke(&refresh_token, &claims.user.id).await?;
+    }
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "User signed out successfully".to_string(),
+    }))
+}
+
+async fn logout_all(claims: Claims, ctx: RequestContext) -> Result<Json<MessageResponse>, Error> {
+    // Tokens issued within the current second are not covered by
+    // `tokens_valid_after`, so the current one is revoked explicitly
+    revoked_token::revoke(&claims.jti, claims.exp).await?;
+    user::invalidate_tokens(&claims.user.id).await?;
+    audit::emit(
+        &ctx,
+        AuditEvent::for_user(AuditEventKind::AllSessionsRevoked, claims.user.id),
+    )
+    .await;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "User signed out from all devices successfully".to_string(),
+    }))
+}
+
+async fn forgot_password(
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    payload
+        .validate()
+        .map_err(|e| Error::bad_request_with_message(format!("Validation error: {}", e)))?;
+
+    // The response is the same whether the email is registered or not, so
+    // this endpoint can't be used to find out which emails have an account
+    let user = User::find_one(doc! { "email": &payload.email }, None).await?;
+    if let Some(user) = user {
+        let user_id = user.id.unwrap();
+        let ttl = SETTINGS.auth.password_reset_ttl;
+        let reset_token = one_time_token::issue(&user_id, TokenPurpose::PasswordReset, ttl).await?;
+
+        let link = mailer::link("/reset-password", &reset_token);
+        let email = Email::new(
+            user.email,
+            "Reset your password",
+            format!(
+                "Hi {},\n\nUse the following link to choose a new password:\n\n{}\n\n\
+                The link expires in {} minutes. If you didn't ask to reset your password, \
+                you can ignore this email.",
+                user.first_name,
+                link,
+                ttl / 60
+            ),
+        );
+
+        if let Err(err) = mailer::mailer().send(email).await {
+            error!("Failed to send password reset email: {}", err);
+        }
+    }
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "If the email is registered, a password reset link was sent to it".to_string(),
+    }))
+}
+
+async fn reset_password(
+    ctx: RequestContext,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    // The token is only used once the new password is accepted, so users can
+    // try again with another one
+    let reset_token =
+        one_time_token::find_valid(&payload.token, TokenPurpose::PasswordReset).await?;
+    let user = User::find_by_id(&reset_token.user)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    password_policy::check(
+        "password",
+        &payload.password,
+        &[&user.email, &user.first_name, &user.last_name],
+    )
+    .await?;
+
+    let reset_token = one_time_token::consume(&payload.token, TokenPurpose::PasswordReset).await?;
+
+    let password_hash = user::hash_password(payload.password).await?;
+    User::update_one(
+        doc! { "_id": &reset_token.user },
+        doc! { "$set": { "password": password_hash, "updated_at": date::now() } },
+        None,
+    )
+    .await?;
+
+    // Sign out every device, the old password may have been compromised
+    user::invalidate_tokens(&reset_token.user).await?;
+
+    // Resetting the password also unlocks accounts locked after too many
+    // failed signins
+    user::unlock(&user).await?;
+    audit::emit(
+        &ctx,
+        AuditEvent::for_user(AuditEventKind::PasswordReset, reset_token.user),
+    )
+    .await;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Password reset successfully".to_string(),
+    }))
+}
+
+async fn verify_email(
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let verification_token =
+        one_time_token::consume(&payload.token, TokenPurpose::EmailVerification).await?;
+
+    let now = date::now();
+    User::update_one(
+        doc! { "_id": &verification_token.user, "email_verified_at": null },
+        doc! { "$set": { "email_verified_at": now, "updated_at": now } },
+        None,
+    )
+    .await?;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Email verified successfully".to_string(),
+    }))
+}
+
+async fn verify_email_change(
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let change_token = one_time_token::consume(&payload.token, TokenPurpose::EmailChange).await?;
+
+    let user = User::find_by_id(&change_token.user)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    let pending_email = user
+        .pending_email
+        .ok_or_else(|| Error::bad_request_with_message("Invalid or expired token".to_string()))?;
+
+    if User::exists(doc! { "email": &pending_email }).await? {
+        return Err(Error::bad_request_with_message(
+            "Email already registered".to_string(),
+        ));
+    }
+
+    let now = date::now();
+    User::update_one(
+        doc! { "_id": &change_token.user },
+        doc! {
+            "$set": { "email": pending_email, "email_verified_at": now, "updated_at": now },
+            "$unset": { "pending_email": "" }
+        },
+        None,
+    )
+    .await?;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Email changed successfully".to_string(),
+    }))
+}
+
+async fn resend_verification_email(
+    Claims { user, .. }: Claims,
+) -> Result<Json<MessageResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    if user.is_email_verified() {
+        return Err(Error::bad_request_with_message(
+            "Email already verified".to_string(),
+        ));
+    }
+
+    account::send_verification_email(&user).await?;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Verification email sent".to_string(),
+    }))
+}
+
+async fn oidc_authorize(
+    Path(provider): Path<String>,
+) -> Result<Json<OidcAuthorizeResponse>, Error> {
+    let state = opaque_token::generate();
+    let nonce = opaque_token::generate();
+    let code_verifier = opaque_token::generate();
+
+    let authorization_url =
+        oidc::authorization_url(&provider, &state, &nonce, &code_verifier).await?;
+
+    let oidc_state = OidcState::new(provider, opaque_token::hash(&state), nonce, code_verifier);
+    OidcState::create(oidc_state).await?;
+
+    Ok(Json(OidcAuthorizeResponse {
+        success: true,
+        message: "Redirect the user to the authorization URL".to_string(),
+        data: OidcAuthorizeResponseData {
+            authorization_url,
+            state,
+        },
+    }))
+}
+
+async fn oidc_callback(
+    Path(provider): Path<String>,
+    ctx: RequestContext,
+    Json(payload): Json<OidcCallbackRequest>,
+) -> Result<Json<SigninResponse>, Error> {
+    // States can only be used once
+    let oidc_state = OidcState::find_one_and_delete(doc! {
+        "provider": &provider,
+        "state_hash": opaque_token::hash(&payload.state),
+        "expires_at": { "$gt": date::now() }
+    })
+    .await?
+    .ok_or_else(|| Error::bad_request_with_message("Invalid or expired state".to_string()))?;
+
+    let claims = oidc::exchange_code(
+        &provider,
+        &payload.code,
+        &oidc_state.code_verifier,
+        &oidc_state.nonce,
+    )
+    .await?;
+
+    let user = account::authenticate_identity(&provider, claims, &ctx).await?;
+
+    let device = Device::new(payload.device_name, ctx.user_agent, ctx.ip);
+    complete_signin(user, device).await
+}
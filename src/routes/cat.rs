@@ -16,7 +16,7 @@ use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder, Respo
 use crate::utils::models::ModelExt;
 use crate::utils::pagination::Pagination;
 use crate::utils::to_object_id::to_object_id;
-use crate::utils::token::TokenUser;
+use crate::utils::token::Claims;
 
 pub fn create_route() -> Router {
     Router::new()
@@ -27,7 +27,10 @@ pub fn create_route() -> Router {
         .route("/cats/:id", put(update_cat_by_id))
 }
 
-async fn create_cat(user: TokenUser, Json(payload): Json<CreateCat>) -> Response<PublicCat> {
+async fn create_cat(
+    Claims { user, .. }: Claims,
+    Json(payload): Json<CreateCat>,
+) -> Response<PublicCat> {
     let cat = Cat::new(user.id, payload.name);
     let cat = Cat::create(cat).await?;
     let res = PublicCat::from(cat);
@@ -40,7 +43,10 @@ async fn create_cat(user: TokenUser, Json(payload): Json<CreateCat>) -> Response
     Ok(res)
 }
 
-async fn query_cats(user: TokenUser, pagination: Pagination) -> Response<Vec<PublicCat>> {
+async fn query_cats(
+    Claims { user, .. }: Claims,
+    pagination: Pagination,
+) -> Response<Vec<PublicCat>> {
     let options = FindOptions::builder()
         .sort(doc! { "created_at": -1_i32 })
         .skip(pagination.offset)
@@ -63,7 +69,10 @@ async fn query_cats(user: TokenUser, pagination: Pagination) -> Response<Vec<Pub
     Ok(res)
 }
 
-async fn get_cat_by_id(user: TokenUser, Path(id): Path<String>) -> Result<Json<PublicCat>, Error> {
+async fn get_cat_by_id(
+    Claims { user, .. }: Claims,
+    Path(id): Path<String>,
+) -> Result<Json<PublicCat>, Error> {
     let cat_id = to_object_id(id)?;
     let cat = Cat::find_one(doc! { "_id": cat_id, "user": &user.id }, None)
         .await?
@@ -82,7 +91,7 @@ async fn get_cat_by_id(user: TokenUser, Path(id): Path<String>) -> Result<Json<P
 }
 
 async fn remove_cat_by_id(
-    user: TokenUser,
+    Claims { user, .. }: Claims,
     Path(id): Path<String>,
 ) -> Result<CustomResponse<()>, Error> {
     let cat_id = to_object_id(id)?;
@@ -101,7 +110,7 @@ async fn remove_cat_by_id(
 }
 
 async fn update_cat_by_id(
-    user: TokenUser,
+    Claims { user, .. }: Claims,
     Path(id): Path<String>,
     Json(payload): Json<UpdateCat>,
 ) -> Result<Json<PublicCat>, Error> {
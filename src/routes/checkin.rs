@@ -1,35 +1,54 @@
 // In src/routes/checkin.rs
+use axum::http::StatusCode; // Add this import for StatusCode
 use axum::{
-    extract::Query,
-    routing::{get, post},
+    extract::{Path, Query},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
-use axum::http::StatusCode;  // Add this import for StatusCode
-use bson::{doc, DateTime};
-use chrono::{Datelike, NaiveDate, Utc};
+use bson::{doc, oid::ObjectId, Bson, Document};
+use chrono::{Days, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tracing::debug;
-use validator::Validate;  // Add this import for the validate attribute
+use validator::Validate; // Add this import for the validate attribute
+use validator::ValidationErrors;
 
-use crate::errors::Error;
+use crate::errors::{invalid_field, Error};
 use crate::models::checkin::{Checkin, PublicCheckin};
+use crate::models::checkin_summary;
+use crate::models::tag;
+use crate::models::tag::Tag;
+use crate::models::user;
+use crate::models::user::User;
+use crate::settings::SETTINGS;
+use crate::utils::authenticate_request::{scope, RequireScope};
 use crate::utils::custom_response::CustomResponseResult as Response;
 use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder, ResponsePagination};
+use crate::utils::date;
+use crate::utils::date::Date;
+use crate::utils::emotion_wheel;
 use crate::utils::models::ModelExt;
-use crate::utils::token::TokenUser;
 use crate::utils::pagination::Pagination;
+use crate::utils::to_object_id::to_object_id;
 
 pub fn create_route() -> Router {
     Router::new()
         .route("/api/checkin", post(create_checkin))
         .route("/api/checkin", get(get_user_checkins))
+        .route("/api/checkin/stats", get(get_checkin_stats))
+        .route("/api/checkin/summary", get(get_checkin_summary))
+        .route("/api/checkin/:id", get(get_checkin_by_id))
+        .route("/api/checkin/:id", patch(update_checkin_by_id))
+        .route("/api/checkin/:id", delete(remove_checkin_by_id))
 }
 
-#[derive(Debug, Deserialize, Validate)]  // Now Validate trait is properly imported
+#[derive(Debug, Deserialize, Validate)] // Now Validate trait is properly imported
 pub struct CreateCheckinRequest {
     #[validate(range(min = 1, max = 5))]
     pub mood_rating: u8,
     pub primary_emotion: String,
+    pub secondary_emotions: Option<Vec<String>>,
     #[validate(range(min = 1, max = 5))]
     pub intensity: u8,
     #[validate(range(min = 1, max = 5))]
@@ -39,101 +58,395 @@ pub struct CreateCheckinRequest {
     #[validate(range(min = 1, max = 5))]
     pub wellbeing: u8,
     pub notes: Option<String>,
+    /// Ids of tags of the user's vocabulary.
+    pub tags: Option<Vec<String>>,
 }
 
 async fn create_checkin(
-    user: TokenUser, 
-    Json(payload): Json<CreateCheckinRequest>
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Json(payload): Json<CreateCheckinRequest>,
 ) -> Response<PublicCheckin> {
     // Validate the payload with validator
-    payload.validate().map_err(|e| Error::bad_request_with_message(format!("Validation error: {:?}", e)))?;
-    
-    // Validate primary_emotion is in the valid set
-    if !crate::models::checkin::valid_emotions().contains(&payload.primary_emotion.as_str()) {
-        return Err(Error::bad_request_with_message("Invalid primary emotion".to_string()));
+    payload
+        .validate()
+        .map_err(|e| Error::bad_request_with_message(format!("Validation error: {:?}", e)))?;
+
+    // Users may be required to verify their email before checking in
+    if SETTINGS.auth.require_verified_email_for_checkin {
+        let user = User::find_by_id(&user.id)
+            .await?
+            .ok_or_else(Error::not_found)?;
+        if !user.is_email_verified() {
+            return Err(Error::forbidden_with_message(
+                "Email not verified".to_string(),
+            ));
+        }
+    }
+
+    // Validate the emotions against the emotion wheel
+    if !emotion_wheel::is_core(&payload.primary_emotion) {
+        return Err(Error::bad_request_with_message(
+            "Invalid primary emotion".to_string(),
+        ));
     }
-    
+    let secondary_emotions = secondary_emotions(
+        &payload.primary_emotion,
+        payload.secondary_emotions.unwrap_or_default(),
+    )?;
+    let tags = checkin_tags(&user.id, payload.tags.unwrap_or_default()).await?;
+
+    let tz = user::timezone(&user.id).await?;
+
     let checkin = Checkin::new(
         user.id,
         payload.mood_rating,
         payload.primary_emotion,
+        secondary_emotions,
         payload.intensity,
         payload.energy_level,
         payload.stress_level,
         payload.wellbeing,
         payload.notes,
+        tags,
     );
-    
+
     let checkin = Checkin::create(checkin).await?;
+    checkin_summary::record_created(tz, &checkin).await;
     let public_checkin = PublicCheckin::from(checkin);
-    
+
     let res = CustomResponseBuilder::new()
         .body(public_checkin)
         .status_code(StatusCode::CREATED)
         .build();
-        
+
     Ok(res)
 }
 
+/// Most secondary emotions a check-in can record.
+const MAX_SECONDARY_EMOTIONS: usize = 5;
+
+/// Checks secondary emotions against the emotion wheel and drops duplicates.
+fn secondary_emotions(primary_emotion: &str, emotions: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut secondary: Vec<String> = Vec::with_capacity(emotions.len());
+
+    for emotion in emotions {
+        if !emotion_wheel::is_known(&emotion) || emotion == primary_emotion {
+            return Err(Error::bad_request_with_message(format!(
+                "Invalid secondary emotion {}",
+                emotion
+            )));
+        }
+        if !secondary.contains(&emotion) {
+            secondary.push(emotion);
+        }
+    }
+
+    if secondary.len() > MAX_SECONDARY_EMOTIONS {
+        return Err(Error::bad_request_with_message(format!(
+            "A check-in can't have more than {} secondary emotions",
+            MAX_SECONDARY_EMOTIONS
+        )));
+    }
+
+    Ok(secondary)
+}
+
+/// Most tags a check-in can have.
+const MAX_CHECKIN_TAGS: usize = 20;
+
+/// Parses the tags of a check-in, which have to be in the user's vocabulary.
+async fn checkin_tags(user_id: &ObjectId, tags: Vec<String>) -> Result<Vec<ObjectId>, Error> {
+    let mut ids: Vec<ObjectId> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let id = ObjectId::parse_str(&tag)
+            .map_err(|_| Error::bad_request_with_message(format!("Invalid tag {}", tag)))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    if ids.len() > MAX_CHECKIN_TAGS {
+        return Err(Error::bad_request_with_message(format!(
+            "A check-in can't have more than {} tags",
+            MAX_CHECKIN_TAGS
+        )));
+    }
+    if !tag::all_owned(user_id, &ids).await? {
+        return Err(Error::bad_request_with_message("Unknown tag".to_string()));
+    }
+
+    Ok(ids)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CheckinQueryParams {
-    month: Option<u32>,  // Month number (1-12)
-    year: Option<i32>,   // Year (e.g., 2025)
+    month: Option<u32>, // Month number (1-12)
+    year: Option<i32>,  // Year (e.g., 2025)
+    /// RFC 3339 timestamps, `from` is inclusive and `to` exclusive.
+    from: Option<String>,
+    to: Option<String>,
+    /// Comma separated list of emotions.
+    primary_emotion: Option<String>,
+    /// Metric bounds are wider than the ratings so out of range values are
+    /// reported along with the other invalid fields.
+    min_mood_rating: Option<i64>,
+    max_mood_rating: Option<i64>,
+    min_intensity: Option<i64>,
+    max_intensity: Option<i64>,
+    min_energy_level: Option<i64>,
+    max_energy_level: Option<i64>,
+    min_stress_level: Option<i64>,
+    max_stress_level: Option<i64>,
+    min_wellbeing: Option<i64>,
+    max_wellbeing: Option<i64>,
+    has_notes: Option<bool>,
+    /// Comma separated list of tag ids, check-ins with any of them match.
+    tag: Option<String>,
+    /// A sortable field, prefixed with `-` for descending order.
+    sort: Option<String>,
+}
+
+/// Fields check-ins can be sorted by.
+const SORTABLE_FIELDS: [&str; 6] = [
+    "created_at",
+    "mood_rating",
+    "intensity",
+    "energy_level",
+    "stress_level",
+    "wellbeing",
+];
+
+/// A metric filter, with the names of its min and max parameters.
+type MetricRange = (
+    &'static str,
+    (&'static str, Option<i64>),
+    (&'static str, Option<i64>),
+);
+
+impl CheckinQueryParams {
+    fn metric_ranges(&self) -> [MetricRange; 5] {
+        [
+            (
+                "mood_rating",
+                ("min_mood_rating", self.min_mood_rating),
+                ("max_mood_rating", self.max_mood_rating),
+            ),
+            (
+                "intensity",
+                ("min_intensity", self.min_intensity),
+                ("max_intensity", self.max_intensity),
+            ),
+            (
+                "energy_level",
+                ("min_energy_level", self.min_energy_level),
+                ("max_energy_level", self.max_energy_level),
+            ),
+            (
+                "stress_level",
+                ("min_stress_level", self.min_stress_level),
+                ("max_stress_level", self.max_stress_level),
+            ),
+            (
+                "wellbeing",
+                ("min_wellbeing", self.min_wellbeing),
+                ("max_wellbeing", self.max_wellbeing),
+            ),
+        ]
+    }
+}
+
+/// Validates the listing filters and turns them into the query and sort of
+/// the check-ins of the user. Every invalid parameter is reported at once.
+async fn checkin_filter(
+    user_id: &ObjectId,
+    params: &CheckinQueryParams,
+) -> Result<(Document, Document), Error> {
+    let mut errors = ValidationErrors::new();
+    let mut query = doc! { "user": user_id };
+    let mut created_at = Document::new();
+
+    match (params.month, params.year) {
+        (Some(month), Some(year)) => {
+            if params.from.is_some() || params.to.is_some() {
+                errors.add("month", invalid_field("Can't be combined with from and to"));
+            } else if !(1..=12).contains(&month) {
+                errors.add("month", invalid_field("Must be between 1 and 12"));
+            } else {
+                // Months are the ones of the user's time zone, a check-in made
+                // late in the evening of the last day belongs to that month
+                let tz = user::timezone(user_id).await?;
+                match date::month_range(tz, year, month) {
+                    Some((start, end)) => {
+                        created_at.insert("$gte", start);
+                        created_at.insert("$lt", end);
+                    }
+                    None => errors.add("year", invalid_field("Invalid date")),
+                }
+            }
+        }
+        (Some(_), None) => errors.add("year", invalid_field("Is required along with month")),
+        (None, Some(_)) => errors.add("month", invalid_field("Is required along with year")),
+        (None, None) => {}
+    }
+
+    let from = params.from.as_deref().map(parse_timestamp);
+    let to = params.to.as_deref().map(parse_timestamp);
+    match from {
+        Some(Some(from)) => {
+            created_at.insert("$gte", from);
+        }
+        Some(None) => errors.add("from", invalid_field("Must be an RFC 3339 timestamp")),
+        None => {}
+    }
+    match to {
+        Some(Some(to)) => {
+            created_at.insert("$lt", to);
+        }
+        Some(None) => errors.add("to", invalid_field("Must be an RFC 3339 timestamp")),
+        None => {}
+    }
+    if let (Some(Some(from)), Some(Some(to))) = (from, to) {
+        if from >= to {
+            errors.add("to", invalid_field("Must be after from"));
+        }
+    }
+    if !created_at.is_empty() {
+        query.insert("created_at", created_at);
+    }
+
+    if let Some(primary_emotion) = params.primary_emotion.as_deref() {
+        let emotions = primary_emotion
+            .split(',')
+            .map(str::trim)
+            .filter(|emotion| !emotion.is_empty())
+            .collect::<Vec<&str>>();
+
+        if emotions.is_empty() {
+            errors.add(
+                "primary_emotion",
+                invalid_field("Must list at least one emotion"),
+            );
+        } else if emotions
+            .iter()
+            .any(|emotion| !emotion_wheel::is_core(emotion))
+        {
+            errors.add(
+                "primary_emotion",
+                invalid_field("Contains an unknown emotion"),
+            );
+        } else {
+            query.insert("primary_emotion", doc! { "$in": emotions });
+        }
+    }
+
+    for (metric, (min_field, min), (max_field, max)) in params.metric_ranges() {
+        let mut range = Document::new();
+
+        if let Some(min) = min {
+            if (1..=5).contains(&min) {
+                range.insert("$gte", min);
+            } else {
+                errors.add(min_field, invalid_field("Must be between 1 and 5"));
+            }
+        }
+        if let Some(max) = max {
+            if !(1..=5).contains(&max) {
+                errors.add(max_field, invalid_field("Must be between 1 and 5"));
+            } else if min.is_some_and(|min| min > max) {
+                errors.add(
+                    max_field,
+                    invalid_field("Must not be lower than the minimum"),
+                );
+            } else {
+                range.insert("$lte", max);
+            }
+        }
+
+        if !range.is_empty() {
+            query.insert(metric, range);
+        }
+    }
+
+    if let Some(tag) = params.tag.as_deref() {
+        let tags = tag
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(ObjectId::parse_str)
+            .collect::<Result<Vec<ObjectId>, _>>();
+
+        match tags {
+            Ok(tags) if !tags.is_empty() => {
+                query.insert("tags", doc! { "$in": tags });
+            }
+            Ok(_) => errors.add("tag", invalid_field("Must list at least one tag")),
+            Err(_) => errors.add("tag", invalid_field("Contains an invalid tag id")),
+        }
+    }
+
+    // Notes may have been stored empty before they were normalized
+    match params.has_notes {
+        Some(true) => {
+            query.insert("notes", doc! { "$nin": [Bson::Null, ""] });
+        }
+        Some(false) => {
+            query.insert("notes", doc! { "$in": [Bson::Null, ""] });
+        }
+        None => {}
+    }
+
+    let sort = params.sort.as_deref().unwrap_or("-created_at");
+    let (field, direction) = match sort.strip_prefix('-') {
+        Some(field) => (field, -1_i32),
+        None => (sort, 1_i32),
+    };
+    if !SORTABLE_FIELDS.contains(&field) {
+        errors.add("sort", invalid_field("Unknown sort field"));
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    // Newest first among check-ins with the same value
+    let mut sort = doc! { field: direction };
+    if field != "created_at" {
+        sort.insert("created_at", -1_i32);
+    }
+    sort.insert("_id", direction);
+
+    Ok((query, sort))
+}
+
+fn parse_timestamp(value: &str) -> Option<Date> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|date| Date::from_chrono(date.with_timezone(&Utc)))
 }
 
 async fn get_user_checkins(
-    user: TokenUser,
+    RequireScope(user, _): RequireScope<scope::CheckinsRead>,
     Query(params): Query<CheckinQueryParams>,
     pagination: Pagination,
 ) -> Response<Vec<PublicCheckin>> {
-    // Start with a query that filters by user
-    let mut query = doc! { "user": &user.id };
-    
-    // If both month and year are provided, add date filtering
-    if let (Some(month), Some(year)) = (params.month, params.year) {
-        // Validate month
-        if month < 1 || month > 12 {
-            return Err(Error::bad_request_with_message("Month must be between 1 and 12".to_string()));
-        }
-        
-        // Create start and end dates for the month
-        let start_date = match NaiveDate::from_ymd_opt(year, month, 1) {
-            Some(date) => date,
-            None => return Err(Error::bad_request_with_message("Invalid date".to_string())),
-        };
-        
-        // Calculate the first day of the next month
-        let end_month = if month == 12 { 1 } else { month + 1 };
-        let end_year = if month == 12 { year + 1 } else { year };
-        let end_date = match NaiveDate::from_ymd_opt(end_year, end_month, 1) {
-            Some(date) => date,
-            None => return Err(Error::bad_request_with_message("Invalid date".to_string())),
-        };
-        
-        // Convert to MongoDB datetime format
-        let start_datetime = DateTime::from_chrono(start_date.and_hms_opt(0, 0, 0).unwrap().and_utc());
-        let end_datetime = DateTime::from_chrono(end_date.and_hms_opt(0, 0, 0).unwrap().and_utc());
-        
-        // Add date range to the query
-        query.insert("created_at", doc! {
-            "$gte": start_datetime,
-            "$lt": end_datetime
-        });
-    }
-    
+    let (query, sort) = checkin_filter(&user.id, &params).await?;
+
     // Set up options for pagination and sorting
     let options = wither::mongodb::options::FindOptions::builder()
-        .sort(doc! { "created_at": -1_i32 })  // Newest first
+        .sort(sort)
         .skip(pagination.offset)
         .limit(pagination.limit as i64)
         .build();
-    
+
     // Find checkins matching the query
     let (checkins, count) = Checkin::find_and_count(query, options).await?;
-    
+
     // Convert to public format
-    let checkins = checkins.into_iter().map(Into::into).collect::<Vec<PublicCheckin>>();
-    
+    let checkins = checkins
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<PublicCheckin>>();
+
     // Build response with pagination
     let res = CustomResponseBuilder::new()
         .body(checkins)
@@ -143,7 +456,565 @@ async fn get_user_checkins(
             limit: pagination.limit,
         })
         .build();
-    
+
     debug!("Returning user checkins");
     Ok(res)
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateCheckinRequest {
+    #[validate(range(min = 1, max = 5))]
+    pub mood_rating: Option<u8>,
+    pub primary_emotion: Option<String>,
+    /// Replaces the current secondary emotions.
+    pub secondary_emotions: Option<Vec<String>>,
+    #[validate(range(min = 1, max = 5))]
+    pub intensity: Option<u8>,
+    #[validate(range(min = 1, max = 5))]
+    pub energy_level: Option<u8>,
+    #[validate(range(min = 1, max = 5))]
+    pub stress_level: Option<u8>,
+    #[validate(range(min = 1, max = 5))]
+    pub wellbeing: Option<u8>,
+    /// Empty notes remove the current ones.
+    pub notes: Option<String>,
+    /// Replaces the current tags.
+    pub tags: Option<Vec<String>>,
+}
+
+async fn get_checkin_by_id(
+    RequireScope(user, _): RequireScope<scope::CheckinsRead>,
+    Path(id): Path<String>,
+) -> Result<Json<PublicCheckin>, Error> {
+    let checkin_id = to_object_id(id)?;
+    let checkin = Checkin::find_one(doc! { "_id": checkin_id, "user": &user.id }, None)
+        .await?
+        .map(PublicCheckin::from);
+
+    let checkin = match checkin {
+        Some(checkin) => checkin,
+        None => {
+            debug!("Checkin not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+
+    debug!("Returning checkin");
+    Ok(Json(checkin))
+}
+
+async fn update_checkin_by_id(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateCheckinRequest>,
+) -> Result<Json<PublicCheckin>, Error> {
+    payload.validate()?;
+
+    if let Some(primary_emotion) = &payload.primary_emotion {
+        if !emotion_wheel::is_core(primary_emotion) {
+            return Err(Error::bad_request_with_message(
+                "Invalid primary emotion".to_string(),
+            ));
+        }
+    }
+
+    let checkin_id = to_object_id(id)?;
+    let checkin = Checkin::find_one(doc! { "_id": &checkin_id, "user": &user.id }, None).await?;
+    let checkin = match checkin {
+        Some(checkin) => checkin,
+        None => {
+            debug!("Checkin not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+
+    // Check-ins are a record of how the user felt at the time, only fixing
+    // them shortly after is allowed
+    let now = date::now();
+    let edit_window = SETTINGS.checkin.edit_window;
+    if now.timestamp_millis() - checkin.created_at.timestamp_millis() > edit_window * 1000 {
+        return Err(Error::forbidden_with_message(format!(
+            "Check-ins can only be edited within {} hours of their creation",
+            edit_window / 3600
+        )));
+    }
+
+    let mut set = doc! { "updated_at": now };
+    if let Some(mood_rating) = payload.mood_rating {
+        set.insert("mood_rating", mood_rating as i32);
+    }
+    // Secondary emotions are checked against the primary emotion the
+    // check-in ends up with
+    let primary_emotion = payload
+        .primary_emotion
+        .clone()
+        .unwrap_or_else(|| checkin.primary_emotion.clone());
+    let secondary = match payload.secondary_emotions {
+        Some(secondary) => secondary,
+        None if payload.primary_emotion.is_some() => checkin
+            .secondary_emotions
+            .iter()
+            .filter(|emotion| **emotion != primary_emotion)
+            .cloned()
+            .collect(),
+        None => checkin.secondary_emotions.clone(),
+    };
+    set.insert(
+        "secondary_emotions",
+        secondary_emotions(&primary_emotion, secondary)?,
+    );
+    if let Some(primary_emotion) = payload.primary_emotion {
+        set.insert("primary_emotion", primary_emotion);
+    }
+    if let Some(intensity) = payload.intensity {
+        set.insert("intensity", intensity as i32);
+    }
+    if let Some(energy_level) = payload.energy_level {
+        set.insert("energy_level", energy_level as i32);
+    }
+    if let Some(stress_level) = payload.stress_level {
+        set.insert("stress_level", stress_level as i32);
+    }
+    if let Some(wellbeing) = payload.wellbeing {
+        set.insert("wellbeing", wellbeing as i32);
+    }
+    if let Some(notes) = payload.notes {
+        let notes = Some(notes).filter(|notes| !notes.trim().is_empty());
+        set.insert("notes", notes);
+    }
+    if let Some(tags) = payload.tags {
+        set.insert("tags", checkin_tags(&user.id, tags).await?);
+    }
+
+    let tz = user::timezone(&user.id).await?;
+    let updated = Checkin::find_one_and_update(
+        doc! { "_id": &checkin_id, "user": &user.id },
+        doc! { "$set": set },
+    )
+    .await?;
+
+    let updated = match updated {
+        Some(updated) => updated,
+        None => {
+            debug!("Checkin not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+
+    checkin_summary::record_mood_changed(tz, &updated, checkin.mood_rating).await;
+
+    debug!("Returning checkin");
+    Ok(Json(PublicCheckin::from(updated)))
+}
+
+async fn remove_checkin_by_id(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Path(id): Path<String>,
+) -> Result<CustomResponse<()>, Error> {
+    let checkin_id = to_object_id(id)?;
+    let tz = user::timezone(&user.id).await?;
+    let checkin =
+        Checkin::find_one_and_delete(doc! { "_id": checkin_id, "user": &user.id }).await?;
+
+    let checkin = match checkin {
+        Some(checkin) => checkin,
+        None => {
+            debug!("Checkin not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+
+    checkin_summary::record_removed(tz, &checkin).await;
+
+    let res = CustomResponseBuilder::new()
+        .status_code(StatusCode::NO_CONTENT)
+        .build();
+
+    Ok(res)
+}
+
+/// Metrics aggregated by the stats endpoint.
+const METRICS: [&str; 5] = [
+    "mood_rating",
+    "intensity",
+    "energy_level",
+    "stress_level",
+    "wellbeing",
+];
+
+/// Longest date range the stats can be requested for, in days.
+const MAX_STATS_RANGE_DAYS: i64 = 3660;
+
+/// Days covered by the stats when no range is given.
+const DEFAULT_STATS_RANGE_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsBucket {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsBucket {
+    fn unit(&self) -> &'static str {
+        match self {
+            StatsBucket::Day => "day",
+            StatsBucket::Week => "week",
+            StatsBucket::Month => "month",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckinStatsQueryParams {
+    /// First day of the range, `YYYY-MM-DD` in the user's time zone.
+    from: Option<String>,
+    /// Last day of the range, inclusive.
+    to: Option<String>,
+    #[serde(default)]
+    bucket: StatsBucket,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricStats {
+    mean: f64,
+    min: u8,
+    max: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsStats {
+    count: u64,
+    mood_rating: MetricStats,
+    intensity: MetricStats,
+    energy_level: MetricStats,
+    stress_level: MetricStats,
+    wellbeing: MetricStats,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BucketRow {
+    #[serde(rename = "_id")]
+    start: Date,
+    #[serde(flatten)]
+    stats: MetricsStats,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmotionFrequency {
+    #[serde(rename(deserialize = "_id"))]
+    emotion: String,
+    count: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StatsFacets {
+    overall: Vec<MetricsStats>,
+    buckets: Vec<BucketRow>,
+    emotions: Vec<EmotionFrequency>,
+    tags: Vec<TagRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TagRow {
+    #[serde(rename = "_id")]
+    tag: ObjectId,
+    count: u64,
+    mood_mean: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagStats {
+    tag: String,
+    name: String,
+    count: u64,
+    mood_mean: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketStats {
+    /// First day of the bucket, in the user's time zone.
+    start: String,
+    #[serde(flatten)]
+    stats: MetricsStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckinStats {
+    timezone: String,
+    bucket: StatsBucket,
+    from: String,
+    to: String,
+    /// Stats over the whole range, absent when there are no check-ins.
+    overall: Option<MetricsStats>,
+    /// Only buckets with check-ins are listed.
+    buckets: Vec<BucketStats>,
+    emotions: Vec<EmotionFrequency>,
+    /// Average mood of the check-ins having each tag, to be compared with the
+    /// overall one.
+    tags: Vec<TagStats>,
+}
+
+async fn get_checkin_stats(
+    RequireScope(user, _): RequireScope<scope::CheckinsRead>,
+    Query(params): Query<CheckinStatsQueryParams>,
+) -> Response<CheckinStats> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    let preferences = user.preferences;
+    let tz = preferences.tz();
+
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let to = match params.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => today,
+    };
+    let from = match params.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => to
+            .checked_sub_days(Days::new(DEFAULT_STATS_RANGE_DAYS as u64 - 1))
+            .ok_or_else(day_out_of_range)?,
+    };
+
+    if from > to {
+        return Err(Error::bad_request_with_message(
+            "from must not be after to".to_string(),
+        ));
+    }
+    if (to - from).num_days() >= MAX_STATS_RANGE_DAYS {
+        return Err(Error::bad_request_with_message(format!(
+            "The date range can't exceed {} days",
+            MAX_STATS_RANGE_DAYS
+        )));
+    }
+
+    let start: Date = date::start_of_day(tz, from).into();
+    let day_after = to
+        .checked_add_days(Days::new(1))
+        .ok_or_else(day_out_of_range)?;
+    let end: Date = date::start_of_day(tz, day_after).into();
+
+    // Buckets are truncated in the user's time zone, $dateTrunc needs
+    // MongoDB 5.0 or later
+    let bucket_start = doc! {
+        "$dateTrunc": {
+            "date": "$created_at",
+            "unit": params.bucket.unit(),
+            "timezone": tz.name(),
+            "startOfWeek": bson::to_bson(&preferences.week_start).unwrap(),
+        }
+    };
+
+    let mut buckets = metric_stats(Bson::Document(bucket_start));
+    buckets.push(doc! { "$sort": { "_id": 1 } });
+
+    let pipeline = vec![
+        doc! { "$match": { "user": &user.id, "created_at": { "$gte": start, "$lt": end } } },
+        doc! {
+            "$facet": {
+                "overall": metric_stats(Bson::Null),
+                "buckets": buckets,
+                "emotions": [
+                    { "$group": { "_id": "$primary_emotion", "count": { "$sum": 1 } } },
+                    { "$sort": { "count": -1, "_id": 1 } },
+                ],
+                "tags": [
+                    { "$unwind": "$tags" },
+                    {
+                        "$group": {
+                            "_id": "$tags",
+                            "count": { "$sum": 1 },
+                            "mood_mean": { "$avg": "$mood_rating" },
+                        }
+                    },
+                    { "$sort": { "count": -1, "_id": 1 } },
+                ],
+            }
+        },
+    ];
+
+    let facets = Checkin::aggregate::<StatsFacets>(pipeline)
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let buckets = facets
+        .buckets
+        .into_iter()
+        .map(|row| BucketStats {
+            start: local_day(tz, row.start),
+            stats: row.stats,
+        })
+        .collect::<Vec<BucketStats>>();
+
+    // Tags are named after the aggregation, check-ins only hold their ids
+    let tag_ids = facets
+        .tags
+        .iter()
+        .map(|row| row.tag)
+        .collect::<Vec<ObjectId>>();
+    let names = Tag::find(doc! { "_id": { "$in": tag_ids }, "user": &user.id }, None)
+        .await?
+        .into_iter()
+        .map(|tag| (tag.id.unwrap(), tag.name))
+        .collect::<HashMap<ObjectId, String>>();
+    let tags = facets
+        .tags
+        .into_iter()
+        .filter_map(|row| {
+            Some(TagStats {
+                tag: row.tag.to_hex(),
+                name: names.get(&row.tag)?.clone(),
+                count: row.count,
+                mood_mean: row.mood_mean,
+            })
+        })
+        .collect::<Vec<TagStats>>();
+
+    let stats = CheckinStats {
+        timezone: tz.name().to_string(),
+        bucket: params.bucket,
+        from: from.to_string(),
+        to: to.to_string(),
+        overall: facets.overall.into_iter().next(),
+        buckets,
+        emotions: facets.emotions,
+        tags,
+    };
+
+    let res = CustomResponseBuilder::new().body(stats).build();
+
+    debug!("Returning checkin stats");
+    Ok(res)
+}
+
+/// Days covered by the heatmap when no range is given.
+const DEFAULT_HEATMAP_RANGE_DAYS: i64 = 365;
+
+#[derive(Debug, Deserialize)]
+pub struct CheckinSummaryQueryParams {
+    /// First day of the heatmap, `YYYY-MM-DD` in the user's time zone.
+    from: Option<String>,
+    /// Last day of the heatmap, inclusive.
+    to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeatmapDay {
+    date: String,
+    count: i64,
+    average_mood: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreakSummary {
+    timezone: String,
+    current_streak: u32,
+    longest_streak: u32,
+    last_checkin_date: Option<String>,
+    from: String,
+    to: String,
+    /// Only days with check-ins are listed.
+    heatmap: Vec<HeatmapDay>,
+}
+
+async fn get_checkin_summary(
+    RequireScope(user, _): RequireScope<scope::CheckinsRead>,
+    Query(params): Query<CheckinSummaryQueryParams>,
+) -> Response<StreakSummary> {
+    let tz = user::timezone(&user.id).await?;
+    let today = Utc::now().with_timezone(&tz).date_naive();
+
+    let to = match params.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => today,
+    };
+    let from = match params.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => to
+            .checked_sub_days(Days::new(DEFAULT_HEATMAP_RANGE_DAYS as u64 - 1))
+            .ok_or_else(day_out_of_range)?,
+    };
+
+    if from > to {
+        return Err(Error::bad_request_with_message(
+            "from must not be after to".to_string(),
+        ));
+    }
+    if (to - from).num_days() >= MAX_STATS_RANGE_DAYS {
+        return Err(Error::bad_request_with_message(format!(
+            "The date range can't exceed {} days",
+            MAX_STATS_RANGE_DAYS
+        )));
+    }
+
+    let summary = checkin_summary::load(&user.id, tz).await?;
+
+    let heatmap = summary
+        .active_days()
+        .filter(|(day, _)| *day >= from && *day <= to)
+        .map(|(day, stats)| HeatmapDay {
+            date: day.to_string(),
+            count: stats.count,
+            average_mood: stats.average_mood(),
+        })
+        .collect::<Vec<HeatmapDay>>();
+
+    let res = CustomResponseBuilder::new()
+        .body(StreakSummary {
+            timezone: summary.timezone.clone(),
+            current_streak: summary.current_streak(today),
+            longest_streak: summary.longest_streak(),
+            last_checkin_date: summary.last_checkin_date().map(|day| day.to_string()),
+            from: from.to_string(),
+            to: to.to_string(),
+            heatmap,
+        })
+        .build();
+
+    debug!("Returning checkin summary");
+    Ok(res)
+}
+
+/// Pipeline grouping check-ins by the given expression, with the mean,
+/// minimum and maximum of every metric.
+fn metric_stats(group_by: Bson) -> Vec<Document> {
+    let mut group = doc! { "_id": group_by, "count": { "$sum": 1 } };
+    let mut project = doc! { "_id": 1, "count": 1 };
+
+    for metric in METRICS {
+        let field = format!("${}", metric);
+        group.insert(format!("{}_mean", metric), doc! { "$avg": &field });
+        group.insert(format!("{}_min", metric), doc! { "$min": &field });
+        group.insert(format!("{}_max", metric), doc! { "$max": &field });
+
+        project.insert(
+            metric,
+            doc! {
+                "mean": format!("${}_mean", metric),
+                "min": format!("${}_min", metric),
+                "max": format!("${}_max", metric),
+            },
+        );
+    }
+
+    vec![doc! { "$group": group }, doc! { "$project": project }]
+}
+
+fn parse_day(name: &str, value: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        Error::bad_request_with_message(format!("{} must be a date formatted as YYYY-MM-DD", name))
+    })
+}
+
+// The dates chrono can represent are bounded, ranges can't be moved past them
+fn day_out_of_range() -> Error {
+    Error::bad_request_with_message("The date range is out of the supported dates".to_string())
+}
+
+fn local_day(tz: Tz, date: Date) -> String {
+    date.to_chrono().with_timezone(&tz).date_naive().to_string()
+}
@@ -0,0 +1,151 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::{
+    extract::{Path, Query},
+    routing::{get, post},
+    Json, Router,
+};
+use bson::doc;
+use serde::{Deserialize, Serialize};
+use tokio_util::io::ReaderStream;
+
+use crate::errors::Error;
+use crate::jobs;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::data_export::{DataExport, ExportStatus};
+use crate::services::audit;
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+use crate::utils::request_context::RequestContext;
+use crate::utils::to_object_id::to_object_id;
+use crate::utils::token::Claims;
+
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/users/me/exports", post(request_export))
+        .route("/api/users/me/exports/:id", get(get_export))
+        .route("/api/exports/download", get(download_export))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportResponseData {
+    #[serde(rename = "exportId")]
+    export_id: String,
+    status: ExportStatus,
+    #[serde(rename = "expiresAt")]
+    expires_at: String,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+}
+
+impl From<DataExport> for ExportResponseData {
+    fn from(export: DataExport) -> Self {
+        Self {
+            export_id: export.id.unwrap().to_hex(),
+            status: export.status,
+            expires_at: export.expires_at.to_chrono().to_rfc3339(),
+            created_at: export.created_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportResponse {
+    success: bool,
+    message: String,
+    data: ExportResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadExportQuery {
+    token: String,
+}
+
+async fn request_export(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+) -> Result<(StatusCode, Json<ExportResponse>), Error> {
+    // Only one export is built at a time for each user
+    let pending =
+        DataExport::find_one(doc! { "user": &user.id, "status": "pending" }, None).await?;
+
+    let export = match pending {
+        Some(export) => export,
+        None => {
+            let export = DataExport::create(DataExport::new(user.id)).await?;
+            jobs::data_export::spawn(export.id.unwrap());
+
+            let event = AuditEvent::for_user(AuditEventKind::DataExportRequested, user.id)
+                .details(doc! { "export": export.id });
+            audit::emit(&ctx, event).await;
+            export
+        }
+    };
+
+    let res = ExportResponse {
+        success: true,
+        message: "Export requested, you will receive a download link by email".to_string(),
+        data: ExportResponseData::from(export),
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(res)))
+}
+
+async fn get_export(
+    Claims { user, .. }: Claims,
+    Path(id): Path<String>,
+) -> Result<Json<ExportResponse>, Error> {
+    let export_id = to_object_id(id)?;
+    let export = DataExport::find_one(doc! { "_id": export_id, "user": &user.id }, None)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(ExportResponse {
+        success: true,
+        message: "Export retrieved successfully".to_string(),
+        data: ExportResponseData::from(export),
+    }))
+}
+
+// The token from the emailed link is enough to download the archive, it
+// expires along with the archive.
+async fn download_export(
+    ctx: RequestContext,
+    Query(query): Query<DownloadExportQuery>,
+) -> Result<(HeaderMap, Body), Error> {
+    let export = DataExport::find_one(
+        doc! {
+            "download_token_hash": opaque_token::hash(&query.token),
+            "status": "ready",
+            "expires_at": { "$gt": date::now() }
+        },
+        None,
+    )
+    .await?
+    .ok_or_else(|| Error::bad_request_with_message("Invalid or expired token".to_string()))?;
+
+    let file = tokio::fs::File::open(export.path()).await?;
+
+    // Whoever has the link can download the export, the owner is recorded as
+    // the subject only
+    let event = AuditEvent::new(AuditEventKind::DataExportDownloaded)
+        .subject(export.user)
+        .details(doc! { "export": export.id });
+    audit::emit(&ctx, event).await;
+    let filename = format!(
+        "mindfulme-export-{}.zip",
+        export.created_at.to_chrono().format("%Y-%m-%d")
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, "application/zip".parse().unwrap());
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}\"", filename)
+            .parse()
+            .unwrap(),
+    );
+
+    Ok((headers, Body::from_stream(ReaderStream::new(file))))
+}
@@ -0,0 +1,59 @@
+use axum::extract::Query;
+use axum::http::{header, HeaderMap};
+use axum::{routing::get, Router};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::utils::custom_response::CustomResponseBuilder;
+use crate::utils::custom_response::CustomResponseResult as Response;
+use crate::utils::emotion_wheel;
+use crate::utils::emotion_wheel::LocalizedEmotion;
+
+pub fn create_route() -> Router {
+    Router::new().route("/api/emotions", get(get_emotions))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmotionsQueryParams {
+    /// Takes precedence over the `Accept-Language` header.
+    locale: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmotionsResponse {
+    /// The locale of the labels, which may be less specific than the one
+    /// requested.
+    locale: String,
+    emotions: Vec<LocalizedEmotion>,
+}
+
+// The wheel is the same for everyone, so it's served without authentication
+// and clients can show it before signing in.
+async fn get_emotions(
+    Query(params): Query<EmotionsQueryParams>,
+    headers: HeaderMap,
+) -> Response<EmotionsResponse> {
+    let requested = params.locale.or_else(|| preferred_language(&headers));
+
+    let wheel = emotion_wheel::wheel();
+    let locale = wheel.resolve_locale(requested.as_deref().unwrap_or_default());
+    let emotions = wheel.localized(&locale);
+
+    let res = CustomResponseBuilder::new()
+        .body(EmotionsResponse { locale, emotions })
+        .build();
+
+    debug!("Returning emotion wheel");
+    Ok(res)
+}
+
+// Only the first language is considered, quality values aren't
+fn preferred_language(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .and_then(|language| language.split(';').next())
+        .map(|language| language.trim().to_string())
+        .filter(|language| !language.is_empty() && language != "*")
+}
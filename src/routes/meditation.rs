@@ -1,17 +1,18 @@
 use axum::{
     extract::{Json, State},
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    http::{header, HeaderMap},
     routing::post,
     Router,
 };
 use serde::{Deserialize, Serialize};
-use std::{env, io::Write, path::PathBuf, fs::File};
-use tracing::{debug, info, error};
+use std::{env, fs::File, io::Write, path::PathBuf};
+use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::errors::Error;
-use crate::utils::token::TokenUser;
+use crate::models::meditation_track::{MeditationSession, MeditationTrack, MUSIC_DIR};
+use crate::utils::authenticate_request::{scope, RequireScope};
+use crate::utils::models::ModelExt;
 use axum::extract::Path;
 use axum::routing::get;
 
@@ -45,13 +46,13 @@ pub struct AppState {
 
 pub fn create_route() -> Router {
     // Create music directory if it doesn't exist
-    let music_dir = PathBuf::from("./meditation_music");
+    let music_dir = PathBuf::from(MUSIC_DIR);
     std::fs::create_dir_all(&music_dir).expect("Failed to create music directory");
-    
+
     // Get HuggingFace token from environment
     let hf_token = env::var("HUGGINGFACE_API_TOKEN")
         .unwrap_or_else(|_| "DEFAULT_TOKEN_REPLACE_ME".to_string());
-    
+
     let state = AppState {
         hf_token,
         music_dir: music_dir.clone(),
@@ -65,7 +66,7 @@ pub fn create_route() -> Router {
 }
 
 async fn generate_music(
-    user: TokenUser, // Add user authentication
+    RequireScope(user, _): RequireScope<scope::MeditationGenerate>,
     State(state): State<AppState>,
     Json(payload): Json<GenerateMusicRequest>,
 ) -> Result<Json<GenerateMusicResponse>, Error> {
@@ -76,7 +77,7 @@ async fn generate_music(
     // Generate a unique filename
     let filename = format!("{}.mp3", Uuid::new_v4());
     let file_path = state.music_dir.join(&filename);
-    
+
     // Prepare request to HuggingFace API
     let api_url = "https://router.huggingface.co/hf-inference/models/facebook/musicgen-small";
     let api_payload = serde_json::json!({
@@ -84,7 +85,7 @@ async fn generate_music(
     });
 
     info!("Calling HuggingFace API to generate music");
-    
+
     // Build the request manually without using reqwest
     let client = reqwest::Client::new();
     let response = client
@@ -97,24 +98,41 @@ async fn generate_music(
 
     // Check if the request was successful
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(Error::bad_request_with_message(format!("API returned error: {}", error_text)));
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(Error::bad_request_with_message(format!(
+            "API returned error: {}",
+            error_text
+        )));
     }
 
     // Get the response bytes (audio file)
-    let audio_bytes = response.bytes().await
-        .map_err(|e| Error::bad_request_with_message(format!("Failed to get response bytes: {}", e)))?;
+    let audio_bytes = response.bytes().await.map_err(|e| {
+        Error::bad_request_with_message(format!("Failed to get response bytes: {}", e))
+    })?;
 
     // Save the audio file
     let mut file = File::create(&file_path)
         .map_err(|e| Error::bad_request_with_message(format!("Failed to create file: {}", e)))?;
-    
+
     file.write_all(&audio_bytes)
         .map_err(|e| Error::bad_request_with_message(format!("Failed to write file: {}", e)))?;
 
+    // Record the owner of the file so it is removed along with the account
+    let session = MeditationSession {
+        duration: payload.duration,
+        meditation_type: payload.meditation_type,
+        music_atmosphere: payload.music_atmosphere,
+        focus_area: payload.focus_area,
+        background: payload.background,
+    };
+    MeditationTrack::create(MeditationTrack::new(user.id, filename.clone(), session)).await?;
+
     // Return the URL to the generated music
     let music_url = format!("/v1/meditation/music/{}", filename);
-    
+
     Ok(Json(GenerateMusicResponse { music_url }))
 }
 
@@ -162,24 +180,26 @@ async fn serve_audio_file(
     State(state): State<AppState>,
 ) -> Result<(HeaderMap, Vec<u8>), Error> {
     let path = state.music_dir.join(&filename);
-    
+
     // Check if file exists
     if !path.exists() {
         return Err(Error::not_found());
     }
-    
+
     // Read file
     let audio_data = tokio::fs::read(path)
         .await
         .map_err(|e| Error::bad_request_with_message(format!("Failed to read file: {}", e)))?;
-    
+
     // Set headers
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, "audio/mpeg".parse().unwrap());
     headers.insert(
-        header::CONTENT_DISPOSITION, 
-        format!("attachment; filename=\"{}\"", filename).parse().unwrap()
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}\"", filename)
+            .parse()
+            .unwrap(),
     );
-    
+
     Ok((headers, audio_data))
-}
\ No newline at end of file
+}
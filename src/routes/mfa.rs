@@ -0,0 +1,201 @@
+use axum::{routing::post, Json, Router};
+use bson::doc;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{AuthenticateError, Error};
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::user;
+use crate::models::user::User;
+use crate::services::audit;
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::opaque_token;
+use crate::utils::request_context::RequestContext;
+use crate::utils::token::Claims;
+use crate::utils::totp;
+
+// Only access tokens obtained by signing in are accepted by these routes,
+// personal access tokens can't change how the user signs in.
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/auth/mfa/totp/enroll", post(enroll_totp))
+        .route("/api/auth/mfa/totp/confirm", post(confirm_totp))
+        .route("/api/auth/mfa/totp/disable", post(disable_totp))
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnrollTotpResponseData {
+    secret: String,
+    #[serde(rename = "otpauthUri")]
+    otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnrollTotpResponse {
+    success: bool,
+    message: String,
+    data: EnrollTotpResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmTotpRequest {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfirmTotpResponseData {
+    #[serde(rename = "recoveryCodes")]
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfirmTotpResponse {
+    success: bool,
+    message: String,
+    data: ConfirmTotpResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableTotpRequest {
+    /// Required unless the account has no password, like the accounts made
+    /// by signing in with an identity provider.
+    password: Option<String>,
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    success: bool,
+    message: String,
+}
+
+async fn enroll_totp(Claims { user, .. }: Claims) -> Result<Json<EnrollTotpResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    if user.is_two_factor_enabled() {
+        return Err(Error::bad_request_with_message(
+            "Two-factor authentication already enabled".to_string(),
+        ));
+    }
+
+    // Enrolling again before confirming replaces the pending secret
+    let secret = totp::generate_secret();
+    let otpauth_uri = totp::otpauth_uri(&secret, &user.email)?;
+    User::update_one(
+        doc! { "_id": &user.id },
+        doc! { "$set": {
+            "two_factor": { "secret": &secret, "enabled_at": null, "recovery_codes": [] },
+            "updated_at": date::now()
+        } },
+        None,
+    )
+    .await?;
+
+    Ok(Json(EnrollTotpResponse {
+        success: true,
+        message: "Confirm the enrollment with a code from your authenticator app".to_string(),
+        data: EnrollTotpResponseData {
+            secret,
+            otpauth_uri,
+        },
+    }))
+}
+
+async fn confirm_totp(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+    Json(payload): Json<ConfirmTotpRequest>,
+) -> Result<Json<ConfirmTotpResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    let two_factor = match &user.two_factor {
+        Some(two_factor) if two_factor.enabled_at.is_none() => two_factor,
+        _ => {
+            return Err(Error::bad_request_with_message(
+                "No pending two-factor enrollment".to_string(),
+            ))
+        }
+    };
+
+    let step = match totp::verify(&two_factor.secret, &user.email, &payload.code)? {
+        Some(step) => step,
+        None => return Err(Error::bad_request_with_message("Invalid code".to_string())),
+    };
+
+    // Recovery codes are only shown once, only their hashes are stored
+    let recovery_codes = totp::generate_recovery_codes();
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| opaque_token::hash(totp::normalize_recovery_code(code)))
+        .collect::<Vec<String>>();
+
+    let now = date::now();
+    User::update_one(
+        doc! { "_id": &user.id },
+        doc! { "$set": {
+            "two_factor.enabled_at": now,
+            "two_factor.recovery_codes": recovery_code_hashes,
+            "two_factor.last_used_step": step,
+            "updated_at": now
+        } },
+        None,
+    )
+    .await?;
+
+    let event = AuditEvent::for_user(AuditEventKind::TwoFactorEnabled, user.id.unwrap());
+    audit::emit(&ctx, event).await;
+
+    Ok(Json(ConfirmTotpResponse {
+        success: true,
+        message: "Two-factor authentication enabled".to_string(),
+        data: ConfirmTotpResponseData { recovery_codes },
+    }))
+}
+
+async fn disable_totp(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+    Json(payload): Json<DisableTotpRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    if !user.is_two_factor_enabled() {
+        return Err(Error::bad_request_with_message(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    }
+
+    // Without a password the code is the only proof left
+    let is_valid = match (&user.password, payload.password) {
+        (None, _) => true,
+        (Some(_), Some(password)) => user::check_password(&user, password).await?,
+        (Some(_), None) => false,
+    };
+    if !is_valid || !user::verify_second_factor(&user, &payload.code).await? {
+        return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+    }
+
+    User::update_one(
+        doc! { "_id": &user.id },
+        doc! {
+            "$unset": { "two_factor": "" },
+            "$set": { "updated_at": date::now() }
+        },
+        None,
+    )
+    .await?;
+
+    let event = AuditEvent::for_user(AuditEventKind::TwoFactorDisabled, user.id.unwrap());
+    audit::emit(&ctx, event).await;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Two-factor authentication disabled".to_string(),
+    }))
+}
@@ -1,6 +1,14 @@
+pub mod admin;
+pub mod api_token;
 pub mod auth;
 pub mod cat;
-pub mod status;
-pub mod user;
 pub mod checkin;
+pub mod data_export;
+pub mod emotion;
 pub mod meditation;
+pub mod mfa;
+pub mod session;
+pub mod status;
+pub mod tag;
+pub mod user;
+pub mod well_known;
@@ -0,0 +1,77 @@
+use axum::http::StatusCode;
+use axum::{
+    extract::Path,
+    routing::{delete, get},
+    Router,
+};
+use bson::doc;
+use tracing::debug;
+use wither::mongodb::options::FindOptions;
+
+use crate::errors::Error;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::session;
+use crate::models::session::{PublicSession, Session};
+use crate::services::audit;
+use crate::utils::custom_response::CustomResponseResult as Response;
+use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::request_context::RequestContext;
+use crate::utils::to_object_id::to_object_id;
+use crate::utils::token::Claims;
+
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/auth/sessions", get(query_sessions))
+        .route("/api/auth/sessions/:id", delete(revoke_session))
+}
+
+async fn query_sessions(claims: Claims) -> Response<Vec<PublicSession>> {
+    let options = FindOptions::builder()
+        .sort(doc! { "last_seen_at": -1_i32 })
+        .build();
+
+    let sessions = Session::find(
+        doc! {
+            "user": &claims.user.id,
+            "revoked_at": null,
+            "expires_at": { "$gt": date::now() }
+        },
+        options,
+    )
+    .await?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| PublicSession::new(session, claims.sid))
+        .collect::<Vec<PublicSession>>();
+
+    let res = CustomResponseBuilder::new().body(sessions).build();
+
+    debug!("Returning sessions");
+    Ok(res)
+}
+
+async fn revoke_session(
+    claims: Claims,
+    ctx: RequestContext,
+    Path(id): Path<String>,
+) -> Result<CustomResponse<()>, Error> {
+    let session_id = to_object_id(id)?;
+
+    if !session::revoke(&session_id, &claims.user.id).await? {
+        debug!("Session not found, returning 404 status code");
+        return Err(Error::not_found());
+    }
+
+    let event = AuditEvent::for_user(AuditEventKind::SessionRevoked, claims.user.id)
+        .details(doc! { "session": session_id, "reason": "revoked" });
+    audit::emit(&ctx, event).await;
+
+    let res = CustomResponseBuilder::new()
+        .status_code(StatusCode::NO_CONTENT)
+        .build();
+
+    Ok(res)
+}
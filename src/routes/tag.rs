@@ -0,0 +1,208 @@
+use axum::http::StatusCode;
+use axum::{
+    extract::Path,
+    routing::{delete, get, patch, post},
+    Json, Router,
+};
+use bson::doc;
+use serde::Deserialize;
+use tracing::debug;
+use validator::Validate;
+use wither::mongodb::options::{FindOptions, UpdateOptions};
+
+use crate::errors::Error;
+use crate::models::tag;
+use crate::models::tag::{PublicTag, Tag, TagKind};
+use crate::utils::authenticate_request::{scope, RequireScope};
+use crate::utils::custom_response::CustomResponseResult as Response;
+use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::to_object_id::to_object_id;
+
+// Tags are part of the check-in data, they are managed with the check-in
+// scopes.
+pub fn create_route() -> Router {
+    Router::new()
+        .route("/api/tags", post(create_tag))
+        .route("/api/tags", get(query_tags))
+        .route("/api/tags/:id", patch(update_tag))
+        .route("/api/tags/:id", delete(remove_tag))
+        .route("/api/tags/:id/merge", post(merge_tag))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTag {
+    name: String,
+    #[serde(default)]
+    kind: TagKind,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateTag {
+    name: Option<String>,
+    kind: Option<TagKind>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeTag {
+    /// The tag the check-ins are moved to.
+    into: String,
+}
+
+async fn create_tag(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Json(payload): Json<CreateTag>,
+) -> Response<PublicTag> {
+    let tag = Tag::new(user.id, payload.name, payload.kind);
+    tag.validate()?;
+
+    // The tag is only inserted when none has its name, checking first would
+    // let concurrent requests both insert and hit the unique index
+    let options = UpdateOptions::builder().upsert(true).build();
+    let result = Tag::update_one(
+        doc! { "user": &user.id, "key": &tag.key },
+        doc! {
+            "$setOnInsert": {
+                "name": &tag.name,
+                "kind": bson::to_bson(&tag.kind).unwrap(),
+                "updated_at": tag.updated_at,
+                "created_at": tag.created_at
+            }
+        },
+        options,
+    )
+    .await?;
+
+    let id = match result.upserted_id.and_then(|id| id.as_object_id()) {
+        Some(id) => id,
+        None => {
+            return Err(Error::bad_request_with_message(
+                "Tag already exists".to_string(),
+            ))
+        }
+    };
+    let tag = Tag {
+        id: Some(id),
+        ..tag
+    };
+
+    let res = CustomResponseBuilder::new()
+        .body(PublicTag::from(tag))
+        .status_code(StatusCode::CREATED)
+        .build();
+
+    Ok(res)
+}
+
+async fn query_tags(
+    RequireScope(user, _): RequireScope<scope::CheckinsRead>,
+) -> Response<Vec<PublicTag>> {
+    let options = FindOptions::builder().sort(doc! { "key": 1_i32 }).build();
+
+    let tags = Tag::find(doc! { "user": &user.id }, options).await?;
+    let tags = tags.into_iter().map(Into::into).collect::<Vec<PublicTag>>();
+
+    let res = CustomResponseBuilder::new().body(tags).build();
+
+    debug!("Returning tags");
+    Ok(res)
+}
+
+async fn update_tag(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateTag>,
+) -> Result<Json<PublicTag>, Error> {
+    let tag_id = to_object_id(id)?;
+    let mut set = doc! { "updated_at": date::now() };
+
+    if let Some(name) = payload.name {
+        let renamed = Tag::new(user.id, name, TagKind::default());
+        renamed.validate()?;
+
+        set.insert("name", renamed.name);
+        set.insert("key", renamed.key);
+    }
+    if let Some(kind) = payload.kind {
+        set.insert("kind", bson::to_bson(&kind).unwrap());
+    }
+
+    // Two tags can't share a name, they have to be merged instead. The unique
+    // index is what tells, checking first would race with other renames.
+    let tag = Tag::find_one_and_update(
+        doc! { "_id": &tag_id, "user": &user.id },
+        doc! { "$set": set },
+    )
+    .await
+    .map_err(|err| {
+        if err.is_duplicate_key() {
+            return Error::bad_request_with_message(
+                "Another tag has this name, merge them instead".to_string(),
+            );
+        }
+        err
+    })?;
+
+    let tag = match tag {
+        Some(tag) => tag,
+        None => {
+            debug!("Tag not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+
+    debug!("Returning tag");
+    Ok(Json(PublicTag::from(tag)))
+}
+
+async fn merge_tag(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Path(id): Path<String>,
+    Json(payload): Json<MergeTag>,
+) -> Result<Json<PublicTag>, Error> {
+    let source = to_object_id(id)?;
+    let target = to_object_id(payload.into)?;
+
+    if source == target {
+        return Err(Error::bad_request_with_message(
+            "A tag can't be merged into itself".to_string(),
+        ));
+    }
+
+    let target = Tag::find_one(doc! { "_id": &target, "user": &user.id }, None).await?;
+    let target = match target {
+        Some(target) => target,
+        None => {
+            debug!("Tag not found, returning 404 status code");
+            return Err(Error::not_found());
+        }
+    };
+    if !Tag::exists(doc! { "_id": &source, "user": &user.id }).await? {
+        debug!("Tag not found, returning 404 status code");
+        return Err(Error::not_found());
+    }
+
+    tag::merge(&user.id, &source, target.id.as_ref().unwrap()).await?;
+
+    debug!("Returning merged tag");
+    Ok(Json(PublicTag::from(target)))
+}
+
+async fn remove_tag(
+    RequireScope(user, _): RequireScope<scope::CheckinsWrite>,
+    Path(id): Path<String>,
+) -> Result<CustomResponse<()>, Error> {
+    let tag_id = to_object_id(id)?;
+
+    if !tag::remove(&user.id, &tag_id).await? {
+        debug!("Tag not found, returning 404 status code");
+        return Err(Error::not_found());
+    }
+
+    let res = CustomResponseBuilder::new()
+        .status_code(StatusCode::NO_CONTENT)
+        .build();
+
+    Ok(res)
+}
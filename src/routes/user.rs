@@ -1,27 +1,107 @@
-use axum::http::StatusCode;
-use axum::{routing::post, Json, Router};
-use bson::doc;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::Response;
+use axum::{
+    middleware,
+    routing::{get, post},
+    Json, Router,
+};
+use bson::{doc, Bson};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, error};
+use validator::{Validate, ValidationErrors};
 
-use crate::errors::{AuthenticateError, Error};
+use crate::errors::{invalid_field, Error};
+use crate::mailer;
+use crate::mailer::Email;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::one_time_token;
+use crate::models::one_time_token::TokenPurpose;
+use crate::models::session;
+use crate::models::session::Device;
 use crate::models::user;
-use crate::models::user::{PublicUser, User};
+use crate::models::user::{Preferences, PublicUser, Units, User, WeekStart};
+use crate::services::account;
+use crate::services::account::NewAccount;
+use crate::services::audit;
 use crate::settings::SETTINGS;
 use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
+use crate::utils::date;
 use crate::utils::models::ModelExt;
-use crate::utils::token;
+use crate::utils::password_policy;
+use crate::utils::request_context::RequestContext;
+use crate::utils::token::Claims;
+
+const MAX_REMINDER_TIMES: usize = 10;
 
 pub fn create_route() -> Router {
+    let router = Router::new()
+        .route(
+            "/api/users/me",
+            get(get_profile)
+                .patch(update_profile)
+                .delete(delete_account),
+        )
+        .route("/api/users/me/password", post(change_password))
+        .route(
+            "/api/users/me/preferences",
+            get(get_preferences).patch(update_preferences),
+        )
+        .route(
+            "/api/users/me/deletion/cancel",
+            post(cancel_account_deletion),
+        );
+
+    if !SETTINGS.legacy_routes.users_enabled {
+        return router;
+    }
+
+    router.merge(legacy_routes())
+}
+
+// The `/users` routes predate `/api/auth` and are kept for older clients
+// until their sunset date. Both share the account flows, so they only differ
+// in their request and response formats. Unknown emails used to be answered
+// with 404 here, they now get the same 401 as wrong passwords so the route
+// doesn't tell which emails are registered.
+fn legacy_routes() -> Router {
+    let sunset = DateTime::parse_from_rfc3339(&SETTINGS.legacy_routes.users_sunset)
+        .expect("Invalid legacy_routes.users_sunset date")
+        .with_timezone(&Utc)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let sunset = HeaderValue::from_str(&sunset).expect("Invalid sunset header value");
+
     Router::new()
         .route("/users", post(create_user))
         .route("/users/authenticate", post(authenticate_user))
+        .layer(middleware::map_response(move |mut res: Response| {
+            let sunset = sunset.clone();
+            async move {
+                let headers = res.headers_mut();
+                headers.insert("deprecation", HeaderValue::from_static("true"));
+                headers.insert("sunset", sunset);
+                headers.insert(
+                    header::LINK,
+                    HeaderValue::from_static("</api/auth/signup>; rel=\"successor-version\""),
+                );
+                res
+            }
+        }))
 }
 
-async fn create_user(Json(body): Json<CreateBody>) -> Result<CustomResponse<PublicUser>, Error> {
-    let password_hash = user::hash_password(body.password).await?;
-    let user = User::new(body.first_name, body.last_name, body.email, password_hash);
-    let user = User::create(user).await?;
+async fn create_user(
+    ctx: RequestContext,
+    Json(body): Json<CreateBody>,
+) -> Result<CustomResponse<PublicUser>, Error> {
+    let new_account = NewAccount {
+        first_name: body.first_name,
+        last_name: body.last_name,
+        email: body.email,
+        password: body.password,
+    };
+    let user = account::signup(new_account, &ctx).await?;
     let res = PublicUser::from(user);
 
     let res = CustomResponseBuilder::new()
@@ -33,54 +113,42 @@ async fn create_user(Json(body): Json<CreateBody>) -> Result<CustomResponse<Publ
 }
 
 async fn authenticate_user(
+    ctx: RequestContext,
     Json(body): Json<AuthorizeBody>,
 ) -> Result<Json<AuthenticateResponse>, Error> {
-    let email = &body.email;
-    let password = &body.password;
-
-    if email.is_empty() {
+    if body.email.is_empty() {
         debug!("Missing email, returning 400 status code");
         return Err(Error::bad_request());
     }
 
-    if password.is_empty() {
+    if body.password.is_empty() {
         debug!("Missing password, returning 400 status code");
         return Err(Error::bad_request());
     }
 
-    let user = User::find_one(doc! { "email": email }, None).await?;
+    let user = account::authenticate(&body.email, body.password, &ctx).await?;
 
-    let user = match user {
-        Some(user) => user,
-        None => {
-            debug!("User not found, returning 401");
-            return Err(Error::not_found());
-        }
-    };
-
-    if !user.is_password_match(password) {
-        debug!("User password is incorrect, returning 401 status code");
-        return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
-    }
-
-    if user.locked_at.is_some() {
-        debug!("User is locked, returning 401");
-        return Err(Error::Authenticate(AuthenticateError::Locked));
+    // The legacy flow has no second step, users with two-factor
+    // authentication have to sign in through /api/auth/signin
+    if user.is_two_factor_enabled() {
+        debug!("User has two-factor authentication enabled, returning 403");
+        return Err(Error::forbidden_with_message(
+            "Two-factor authentication required, sign in with /api/auth/signin".to_string(),
+        ));
     }
 
-    let secret = SETTINGS.auth.secret.as_str();
-    let token = token::create(user.clone(), secret)
-        .map_err(|_| Error::Authenticate(AuthenticateError::TokenCreation))?;
+    let device = Device::new(body.device_name, ctx.user_agent, ctx.ip);
+    let tokens = account::start_session(&user, device).await?;
 
     let res = AuthenticateResponse {
-        access_token: token,
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
         user: PublicUser::from(user),
     };
 
     Ok(Json(res))
 }
 
-// TODO: Validate password length
 #[derive(Debug, Deserialize)]
 struct CreateBody {
     first_name: String,
@@ -93,10 +161,497 @@ struct CreateBody {
 struct AuthorizeBody {
     email: String,
     password: String,
+    device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthenticateResponse {
     pub access_token: String,
+    pub refresh_token: String,
     pub user: PublicUser,
 }
+
+#[derive(Debug, Serialize)]
+pub struct ProfileResponseData {
+    #[serde(rename = "userId")]
+    user_id: String,
+    email: String,
+    #[serde(rename = "firstName")]
+    first_name: String,
+    #[serde(rename = "lastName")]
+    last_name: String,
+    #[serde(rename = "emailVerified")]
+    email_verified: bool,
+    /// Email waiting to be confirmed before replacing `email`.
+    #[serde(rename = "pendingEmail")]
+    pending_email: Option<String>,
+    /// When the account gets erased, if its deletion was requested.
+    #[serde(rename = "deletionScheduledAt")]
+    deletion_scheduled_at: Option<String>,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+    #[serde(rename = "updatedAt")]
+    updated_at: String,
+}
+
+impl From<User> for ProfileResponseData {
+    fn from(user: User) -> Self {
+        Self {
+            user_id: user.id.unwrap().to_hex(),
+            email_verified: user.is_email_verified(),
+            email: user.email,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            pending_email: user.pending_email,
+            deletion_scheduled_at: user
+                .deletion_scheduled_at
+                .map(|date| date.to_chrono().to_rfc3339()),
+            created_at: user.created_at.to_chrono().to_rfc3339(),
+            updated_at: user.updated_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileResponse {
+    success: bool,
+    message: String,
+    data: ProfileResponseData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreferencesResponseData {
+    timezone: String,
+    locale: String,
+    #[serde(rename = "weekStart")]
+    week_start: WeekStart,
+    #[serde(rename = "reminderTimes")]
+    reminder_times: Vec<String>,
+    units: Units,
+}
+
+impl From<Preferences> for PreferencesResponseData {
+    fn from(preferences: Preferences) -> Self {
+        Self {
+            timezone: preferences.timezone,
+            locale: preferences.locale,
+            week_start: preferences.week_start,
+            reminder_times: preferences.reminder_times,
+            units: preferences.units,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreferencesResponse {
+    success: bool,
+    message: String,
+    data: PreferencesResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    timezone: Option<String>,
+    locale: Option<String>,
+    #[serde(rename = "weekStart")]
+    week_start: Option<WeekStart>,
+    #[serde(rename = "reminderTimes")]
+    reminder_times: Option<Vec<String>>,
+    units: Option<Units>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(length(min = 1))]
+    #[serde(rename = "firstName")]
+    first_name: Option<String>,
+    #[validate(length(min = 1))]
+    #[serde(rename = "lastName")]
+    last_name: Option<String>,
+    #[validate(email)]
+    email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    current_password: String,
+    #[serde(rename = "newPassword")]
+    new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    /// Required for users who have a password.
+    password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    success: bool,
+    message: String,
+}
+
+async fn get_profile(Claims { user, .. }: Claims) -> Result<Json<ProfileResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(ProfileResponse {
+        success: true,
+        message: "Profile retrieved successfully".to_string(),
+        data: ProfileResponseData::from(user),
+    }))
+}
+
+async fn update_profile(
+    Claims { user, .. }: Claims,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> Result<Json<ProfileResponse>, Error> {
+    payload
+        .validate()
+        .map_err(|e| Error::bad_request_with_message(format!("Validation error: {}", e)))?;
+
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    let mut set = doc! { "updated_at": date::now() };
+    if let Some(first_name) = payload.first_name {
+        set.insert("first_name", first_name);
+    }
+    if let Some(last_name) = payload.last_name {
+        set.insert("last_name", last_name);
+    }
+
+    // The email only changes once the new address is confirmed, asking for
+    // the current one cancels a pending change
+    let mut email_changed = false;
+    if let Some(email) = payload.email {
+        if email == user.email {
+            set.insert("pending_email", Bson::Null);
+        } else {
+            if User::exists(doc! { "email": &email }).await? {
+                return Err(Error::bad_request_with_message(
+                    "Email already registered".to_string(),
+                ));
+            }
+            set.insert("pending_email", email);
+            email_changed = true;
+        }
+    }
+
+    let user = User::find_one_and_update(doc! { "_id": user.id }, doc! { "$set": set })
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    let message = if email_changed {
+        send_email_change_email(&user).await?;
+        "Profile updated, check your inbox to confirm your new email"
+    } else {
+        "Profile updated successfully"
+    };
+
+    Ok(Json(ProfileResponse {
+        success: true,
+        message: message.to_string(),
+        data: ProfileResponseData::from(user),
+    }))
+}
+
+async fn change_password(
+    claims: Claims,
+    ctx: RequestContext,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<MessageResponse>, Error> {
+    let user = User::find_by_id(&claims.user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    // Users who signed up with an identity provider have no password to
+    // check, they can set one with the password reset flow
+    let is_valid = match user.password.clone() {
+        Some(hash) => user::verify_password(payload.current_password, hash).await?,
+        None => false,
+    };
+    if !is_valid {
+        return Err(Error::bad_request_with_message(
+            "Current password is incorrect".to_string(),
+        ));
+    }
+
+    password_policy::check(
+        "newPassword",
+        &payload.new_password,
+        &[&user.email, &user.first_name, &user.last_name],
+    )
+    .await?;
+
+    let password_hash = user::hash_password(payload.new_password).await?;
+    User::update_one(
+        doc! { "_id": &claims.user.id },
+        doc! { "$set": { "password": password_hash, "updated_at": date::now() } },
+        None,
+    )
+    .await?;
+
+    // Other devices have to sign in again with the new password
+    session::revoke_others(&claims.user.id, claims.sid.as_ref()).await?;
+    audit::emit(
+        &ctx,
+        AuditEvent::for_user(AuditEventKind::PasswordChanged, claims.user.id),
+    )
+    .await;
+
+    Ok(Json(MessageResponse {
+        success: true,
+        message: "Password changed successfully".to_string(),
+    }))
+}
+
+async fn get_preferences(Claims { user, .. }: Claims) -> Result<Json<PreferencesResponse>, Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(PreferencesResponse {
+        success: true,
+        message: "Preferences retrieved successfully".to_string(),
+        data: PreferencesResponseData::from(user.preferences),
+    }))
+}
+
+async fn update_preferences(
+    Claims { user, .. }: Claims,
+    Json(payload): Json<UpdatePreferencesRequest>,
+) -> Result<Json<PreferencesResponse>, Error> {
+    let mut errors = ValidationErrors::new();
+    let mut set = doc! { "updated_at": date::now() };
+
+    if let Some(timezone) = payload.timezone {
+        match timezone.parse::<Tz>() {
+            Ok(tz) => {
+                set.insert("preferences.timezone", tz.name());
+            }
+            Err(_) => errors.add("timezone", invalid_field("Must be an IANA time zone")),
+        }
+    }
+
+    if let Some(locale) = payload.locale {
+        if is_language_tag(&locale) {
+            set.insert("preferences.locale", locale);
+        } else {
+            errors.add("locale", invalid_field("Must be a BCP 47 language tag"));
+        }
+    }
+
+    if let Some(week_start) = payload.week_start {
+        set.insert(
+            "preferences.week_start",
+            bson::to_bson(&week_start).unwrap(),
+        );
+    }
+
+    if let Some(reminder_times) = payload.reminder_times {
+        match parse_reminder_times(&reminder_times) {
+            Some(reminder_times) => {
+                set.insert("preferences.reminder_times", reminder_times);
+            }
+            None => errors.add(
+                "reminderTimes",
+                invalid_field("Must be at most 10 distinct times formatted as HH:MM"),
+            ),
+        }
+    }
+
+    if let Some(units) = payload.units {
+        set.insert("preferences.units", bson::to_bson(&units).unwrap());
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let user = User::find_one_and_update(doc! { "_id": &user.id }, doc! { "$set": set })
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(PreferencesResponse {
+        success: true,
+        message: "Preferences updated successfully".to_string(),
+        data: PreferencesResponseData::from(user.preferences),
+    }))
+}
+
+// Only the shape is checked, `en`, `pt-BR` or `zh-Hant-TW` are accepted
+fn is_language_tag(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Normalizes reminder times to `HH:MM`, sorted. `None` when a time is
+/// invalid, repeated or there are too many of them.
+fn parse_reminder_times(reminder_times: &[String]) -> Option<Vec<String>> {
+    let mut times = reminder_times
+        .iter()
+        .map(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+        .collect::<Option<Vec<NaiveTime>>>()?;
+    times.sort();
+    times.dedup();
+
+    if times.len() != reminder_times.len() || times.len() > MAX_REMINDER_TIMES {
+        return None;
+    }
+
+    Some(
+        times
+            .into_iter()
+            .map(|time| time.format("%H:%M").to_string())
+            .collect(),
+    )
+}
+
+async fn delete_account(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+    payload: Option<Json<DeleteAccountRequest>>,
+) -> Result<(StatusCode, Json<ProfileResponse>), Error> {
+    let user = User::find_by_id(&user.id)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    if user.deletion_scheduled_at.is_some() {
+        return Err(Error::bad_request_with_message(
+            "Account deletion already scheduled".to_string(),
+        ));
+    }
+
+    // Users who have a password confirm the deletion with it
+    if let Some(hash) = user.password.clone() {
+        let password = payload
+            .and_then(|Json(payload)| payload.password)
+            .unwrap_or_default();
+
+        if !user::verify_password(password, hash).await? {
+            return Err(Error::bad_request_with_message(
+                "Password is incorrect".to_string(),
+            ));
+        }
+    }
+
+    let now = date::now();
+    let grace_period = chrono::Duration::seconds(SETTINGS.account_deletion.grace_period);
+    let scheduled_at: date::Date = (now.to_chrono() + grace_period).into();
+
+    let user = User::find_one_and_update(
+        doc! { "_id": user.id },
+        doc! { "$set": { "deletion_scheduled_at": scheduled_at, "updated_at": now } },
+    )
+    .await?
+    .ok_or_else(Error::not_found)?;
+
+    let event = AuditEvent::for_user(AuditEventKind::AccountDeletionRequested, user.id.unwrap())
+        .details(doc! { "scheduled_at": scheduled_at });
+    audit::emit(&ctx, event).await;
+
+    let email = Email::new(
+        user.email.clone(),
+        "Your account will be deleted",
+        format!(
+            "Hi {},\n\nYour account and all of its data will be erased on {}. Until then \
+            you can sign in and cancel the deletion.",
+            user.first_name,
+            scheduled_at.to_chrono().format("%B %-d, %Y")
+        ),
+    );
+    if let Err(err) = mailer::mailer().send(email).await {
+        error!("Failed to send account deletion notice: {}", err);
+    }
+
+    let res = ProfileResponse {
+        success: true,
+        message: "Account deletion scheduled".to_string(),
+        data: ProfileResponseData::from(user),
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(res)))
+}
+
+async fn cancel_account_deletion(
+    Claims { user, .. }: Claims,
+    ctx: RequestContext,
+) -> Result<Json<ProfileResponse>, Error> {
+    // Accounts past their grace period may already be getting erased
+    let user = User::find_one_and_update(
+        doc! {
+            "_id": &user.id,
+            "deletion_scheduled_at": { "$gt": date::now() },
+            "erasing_at": null
+        },
+        doc! {
+            "$set": { "updated_at": date::now() },
+            "$unset": { "deletion_scheduled_at": "" }
+        },
+    )
+    .await?
+    .ok_or_else(|| Error::bad_request_with_message("No account deletion scheduled".to_string()))?;
+
+    audit::emit(
+        &ctx,
+        AuditEvent::for_user(AuditEventKind::AccountDeletionCancelled, user.id.unwrap()),
+    )
+    .await;
+
+    Ok(Json(ProfileResponse {
+        success: true,
+        message: "Account deletion cancelled".to_string(),
+        data: ProfileResponseData::from(user),
+    }))
+}
+
+async fn send_email_change_email(user: &User) -> Result<(), Error> {
+    let pending_email = match &user.pending_email {
+        Some(pending_email) => pending_email.clone(),
+        None => return Ok(()),
+    };
+
+    let ttl = SETTINGS.auth.email_verification_ttl;
+    let token = one_time_token::issue(&user.id.unwrap(), TokenPurpose::EmailChange, ttl).await?;
+
+    let link = mailer::link("/verify-email-change", &token);
+    let email = Email::new(
+        pending_email.clone(),
+        "Confirm your new email",
+        format!(
+            "Hi {},\n\nPlease confirm your new email address using the following link:\n\n{}\n\n\
+            The link expires in {} hours.",
+            user.first_name,
+            link,
+            ttl / 3600
+        ),
+    );
+    mailer::mailer().send(email).await?;
+
+    // The current address is told about the change, in case someone else
+    // requested it. Failing to deliver it shouldn't fail the request.
+    let notice = Email::new(
+        user.email.clone(),
+        "Your email is being changed",
+        format!(
+            "Hi {},\n\nA change of your email address to {} was requested. If you didn't \
+            request it, please change your password.",
+            user.first_name, pending_email
+        ),
+    );
+    if let Err(err) = mailer::mailer().send(notice).await {
+        error!("Failed to send email change notice: {}", err);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use axum::{routing::get, Json, Router};
+use serde_json::Value;
+
+use crate::errors::Error;
+use crate::utils::signing_keys;
+
+pub fn create_route() -> Router {
+    Router::new().route("/.well-known/jwks.json", get(get_jwks))
+}
+
+async fn get_jwks() -> Result<Json<Value>, Error> {
+    Ok(Json(signing_keys::jwks()))
+}
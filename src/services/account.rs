@@ -0,0 +1,337 @@
+use tracing::error;
+use validator::Validate;
+use wither::bson::{doc, Document};
+
+use crate::errors::{AuthenticateError, Error};
+use crate::mailer;
+use crate::mailer::Email;
+use crate::models::audit_event::{AuditEvent, AuditEventKind};
+use crate::models::one_time_token;
+use crate::models::one_time_token::TokenPurpose;
+use crate::models::refresh_token;
+use crate::models::session;
+use crate::models::session::Device;
+use crate::models::signin_throttle;
+use crate::models::signin_throttle::SigninKeys;
+use crate::models::user;
+use crate::models::user::{Identity, User};
+use crate::services::audit;
+use crate::settings::SETTINGS;
+use crate::utils::date;
+use crate::utils::models::ModelExt;
+use crate::utils::oidc::IdTokenClaims;
+use crate::utils::password_policy;
+use crate::utils::request_context::RequestContext;
+use crate::utils::token;
+
+#[derive(Debug, Validate)]
+pub struct NewAccount {
+    #[validate(length(min = 1))]
+    pub first_name: String,
+    #[validate(length(min = 1))]
+    pub last_name: String,
+    #[validate(email)]
+    pub email: String,
+    pub password: String,
+}
+
+/// Tokens of a newly started session.
+#[derive(Debug)]
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// Registers a new account and sends it the email verification link.
+pub async fn signup(account: NewAccount, ctx: &RequestContext) -> Result<User, Error> {
+    account.validate()?;
+    password_policy::check(
+        "password",
+        &account.password,
+        &[&account.email, &account.first_name, &account.last_name],
+    )
+    .await?;
+
+    if User::exists(doc! { "email": &account.email }).await? {
+        return Err(Error::bad_request_with_message(
+            "Email already registered".to_string(),
+        ));
+    }
+
+    let password_hash = user::hash_password(account.password).await?;
+    let user = User::new(
+        account.first_name,
+        account.last_name,
+        account.email,
+        password_hash,
+    );
+    let user = User::create(user).await?;
+    audit::emit(
+        ctx,
+        AuditEvent::for_user(AuditEventKind::Signup, user.id.unwrap()),
+    )
+    .await;
+
+    // Failing to deliver the email shouldn't fail the signup, users can ask
+    // for another one
+    if let Err(err) = send_verification_email(&user).await {
+        error!("Failed to send verification email: {}", err);
+    }
+
+    Ok(user)
+}
+
+/// Checks the email and password of a signin. Failed attempts are throttled
+/// per email and IP, and lock the account once they reach the configured
+/// threshold.
+pub async fn authenticate(
+    email: &str,
+    password: String,
+    ctx: &RequestContext,
+) -> Result<User, Error> {
+    // Back off when there were too many failed attempts
+    let keys = SigninKeys::new(email, ctx.ip);
+    if let Err(err) = signin_throttle::check(&keys).await {
+        signin_failed(ctx, email, None, "throttled").await;
+        return Err(err);
+    }
+
+    let user = match User::find_one(doc! { "email": email }, None).await? {
+        Some(user) => user,
+        None => {
+            signin_throttle::record_failure(&keys).await?;
+            signin_failed(ctx, email, None, "unknown_email").await;
+            return Err(wrong_credentials());
+        }
+    };
+
+    if user.locked_at.is_some() {
+        signin_failed(ctx, email, Some(&user), "locked").await;
+        return Err(Error::Authenticate(AuthenticateError::Locked));
+    }
+
+    if !user::check_password(&user, password.clone()).await? {
+        record_failed_signin(ctx, &user, &keys, "wrong_password").await?;
+        return Err(wrong_credentials());
+    }
+    signin_throttle::clear(&user.email).await?;
+
+    // A failed upgrade is retried on the next signin
+    if let Err(err) = user::upgrade_password_hash(&user, password).await {
+        error!("Failed to upgrade password hash: {}", err);
+    }
+
+    // Users with two-factor authentication are only signed in once they
+    // provide their code
+    if !user.is_two_factor_enabled() {
+        signin_succeeded(ctx, &user, doc! { "method": "password" }).await;
+    }
+
+    Ok(user)
+}
+
+/// Checks the second factor code of a signin started with `authenticate`,
+/// the MFA token identifies the user who passed the first step.
+pub async fn authenticate_second_factor(
+    mfa_token: &str,
+    code: &str,
+    ctx: &RequestContext,
+) -> Result<User, Error> {
+    let token_data =
+        token::decode_mfa_token(mfa_token).map_err(|_| AuthenticateError::InvalidToken)?;
+
+    let user = User::find_by_id(&token_data.claims.mfa_user)
+        .await?
+        .ok_or(AuthenticateError::InvalidToken)?;
+
+    // Codes are throttled the same way passwords are
+    let keys = SigninKeys::new(&user.email, ctx.ip);
+    if let Err(err) = signin_throttle::check(&keys).await {
+        signin_failed(ctx, &user.email, Some(&user), "throttled").await;
+        return Err(err);
+    }
+
+    if user.locked_at.is_some() {
+        signin_failed(ctx, &user.email, Some(&user), "locked").await;
+        return Err(Error::Authenticate(AuthenticateError::Locked));
+    }
+
+    if !user::verify_second_factor(&user, code).await? {
+        record_failed_signin(ctx, &user, &keys, "wrong_code").await?;
+        return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+    }
+    signin_throttle::clear(&user.email).await?;
+
+    signin_succeeded(ctx, &user, doc! { "method": "two_factor" }).await;
+
+    Ok(user)
+}
+
+/// Finds the user of an identity provider account, linking it to the account
+/// with the same email or registering a new one the first time.
+pub async fn authenticate_identity(
+    provider: &str,
+    claims: IdTokenClaims,
+    ctx: &RequestContext,
+) -> Result<User, Error> {
+    let user = find_or_link_identity(provider, claims).await?;
+
+    if user.locked_at.is_some() {
+        signin_failed(ctx, &user.email, Some(&user), "locked").await;
+        return Err(Error::Authenticate(AuthenticateError::Locked));
+    }
+
+    if !user.is_two_factor_enabled() {
+        let details = doc! { "method": "identity_provider", "provider": provider };
+        signin_succeeded(ctx, &user, details).await;
+    }
+
+    Ok(user)
+}
+
+/// Starts a session for an authenticated user and issues its tokens.
+pub async fn start_session(user: &User, device: Device) -> Result<SessionTokens, Error> {
+    let user_id = user.id.unwrap();
+    let session = session::start(&user_id, device).await?;
+    let refresh_token = refresh_token::issue(&user_id, &session).await?;
+    let access_token = token::create(user.clone(), session)?;
+
+    Ok(SessionTokens {
+        access_token,
+        refresh_token,
+    })
+}
+
+pub async fn send_verification_email(user: &User) -> Result<(), Error> {
+    let user_id = user.id.unwrap();
+    let ttl = SETTINGS.auth.email_verification_ttl;
+    let verification_token =
+        one_time_token::issue(&user_id, TokenPurpose::EmailVerification, ttl).await?;
+
+    let link = mailer::link("/verify-email", &verification_token);
+    let email = Email::new(
+        user.email.clone(),
+        "Verify your email",
+        format!(
+            "Hi {},\n\nPlease confirm your email address using the following link:\n\n{}\n\n\
+            The link expires in {} hours.",
+            user.first_name,
+            link,
+            ttl / 3600
+        ),
+    );
+
+    mailer::mailer().send(email).await
+}
+
+fn wrong_credentials() -> Error {
+    Error::unauthorized_with_message("Invalid email or password".to_string())
+}
+
+async fn record_failed_signin(
+    ctx: &RequestContext,
+    user: &User,
+    keys: &SigninKeys,
+    reason: &str,
+) -> Result<(), Error> {
+    signin_failed(ctx, &user.email, Some(user), reason).await;
+
+    if user::record_failed_signin(user, keys).await? {
+        let details = doc! { "reason": "too_many_failed_signins" };
+        let event = AuditEvent::new(AuditEventKind::UserLocked)
+            .subject(user.id.unwrap())
+            .details(details);
+        audit::emit(ctx, event).await;
+    }
+
+    Ok(())
+}
+
+async fn signin_failed(ctx: &RequestContext, email: &str, user: Option<&User>, reason: &str) {
+    let mut event = AuditEvent::new(AuditEventKind::SigninFailed)
+        .details(doc! { "email": email, "reason": reason });
+    if let Some(user) = user {
+        event = event.subject(user.id.unwrap());
+    }
+
+    audit::emit(ctx, event).await;
+}
+
+async fn signin_succeeded(ctx: &RequestContext, user: &User, details: Document) {
+    let event =
+        AuditEvent::for_user(AuditEventKind::SigninSucceeded, user.id.unwrap()).details(details);
+    audit::emit(ctx, event).await;
+}
+
+async fn find_or_link_identity(provider: &str, claims: IdTokenClaims) -> Result<User, Error> {
+    let linked_user = User::find_one(
+        doc! { "identities": { "$elemMatch": { "provider": provider, "subject": &claims.sub } } },
+        None,
+    )
+    .await?;
+
+    if let Some(user) = linked_user {
+        return Ok(user);
+    }
+
+    // Accounts are linked by email, which is only trusted when the provider
+    // verified it. Otherwise anyone could take over an account by signing up
+    // at the provider with its email.
+    let email = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => {
+            return Err(Error::forbidden_with_message(
+                "The identity provider did not verify the email".to_string(),
+            ))
+        }
+    };
+
+    let identity = Identity::new(provider, &claims.sub);
+
+    if let Some(user) = User::find_one(doc! { "email": &email }, None).await? {
+        // An unverified account may have been registered by someone else
+        // with this email, linking it would hand them the victim's identity
+        if !user.is_email_verified() {
+            return Err(Error::forbidden_with_message(
+                "An account with this email exists, verify its email before signing in \
+                with this identity provider"
+                    .to_string(),
+            ));
+        }
+
+        // The password and two-factor authentication are kept, so an account
+        // with two-factor authentication still gets its challenge when
+        // signing in with the identity provider
+        let user_id = user.id.unwrap();
+        let user = User::find_one_and_update(
+            doc! { "_id": user_id, "email_verified_at": { "$ne": null } },
+            doc! {
+                "$push": { "identities": {
+                    "provider": &identity.provider,
+                    "subject": &identity.subject,
+                    "linked_at": identity.linked_at
+                } },
+                "$set": { "updated_at": date::now() }
+            },
+        )
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+        return Ok(user);
+    }
+
+    // Not every provider sends the given and family names separately
+    let name = claims.name.unwrap_or_default();
+    let (name_first, name_last) = name.trim().split_once(' ').unwrap_or((name.trim(), ""));
+    let first_name = claims
+        .given_name
+        .or_else(|| Some(name_first.to_string()).filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+    let last_name = claims
+        .family_name
+        .or_else(|| Some(name_last.trim().to_string()).filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "-".to_string());
+
+    let user = User::new_with_identity(first_name, last_name, email, identity);
+    User::create(user).await
+}
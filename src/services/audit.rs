@@ -0,0 +1,20 @@
+use tracing::error;
+
+use crate::models::audit_event::AuditEvent;
+use crate::utils::models::ModelExt;
+use crate::utils::request_context::RequestContext;
+
+/// Records an audit event along with where the request came from. Failing to
+/// record it doesn't fail the request, the event is logged instead.
+pub async fn emit(ctx: &RequestContext, event: AuditEvent) {
+    let event = AuditEvent {
+        ip: ctx.ip.map(|ip| ip.to_string()),
+        user_agent: ctx.user_agent.clone(),
+        request_id: ctx.request_id.clone(),
+        ..event
+    };
+
+    if let Err(err) = AuditEvent::create(event.clone()).await {
+        error!("Failed to record audit event {:?}: {}", event, err);
+    }
+}
@@ -0,0 +1,4 @@
+// Services hold the flows that are shared by several route sets, so routes
+// only deal with their request and response formats.
+pub mod account;
+pub mod audit;
@@ -1,6 +1,7 @@
 use config::{Config, ConfigError, Environment, File};
 use once_cell::sync::Lazy;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::{env, fmt};
 
 pub static SETTINGS: Lazy<Settings> =
@@ -10,11 +11,23 @@ fn default_host() -> String {
     "127.0.0.1".to_string()
 }
 
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Server {
     pub port: u16,
     #[serde(default = "default_host")]
     pub host: String,
+    /// Whether to read client IPs from the `X-Forwarded-For` header. Only
+    /// enable this behind a proxy that sets it.
+    #[serde(default)]
+    pub trust_proxy: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -31,6 +44,173 @@ pub struct Database {
 #[derive(Debug, Clone, Deserialize)]
 pub struct Auth {
     pub secret: String,
+    /// Lifetime of access tokens, in seconds.
+    pub access_token_ttl: i64,
+    /// Lifetime of refresh tokens, in seconds.
+    pub refresh_token_ttl: i64,
+    /// Lifetime of password reset tokens, in seconds.
+    pub password_reset_ttl: i64,
+    /// Lifetime of email verification tokens, in seconds.
+    pub email_verification_ttl: i64,
+    /// Whether users must verify their email before creating check-ins.
+    pub require_verified_email_for_checkin: bool,
+    /// Lifetime of the token exchanged for an access token once the second
+    /// authentication factor is provided, in seconds.
+    pub mfa_token_ttl: i64,
+    /// Issuer shown by authenticator apps.
+    pub totp_issuer: String,
+    /// Keys tokens are signed and verified with, published at
+    /// `/.well-known/jwks.json`. Tokens are signed with `secret` (HS256) when
+    /// no `signing_kid` is set.
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKey>,
+    /// `kid` of the key new tokens are signed with.
+    pub signing_kid: Option<String>,
+    /// Whether tokens signed with `secret` are still accepted once a
+    /// `signing_kid` is set. Only meant to be turned on while the tokens
+    /// issued before the switch expire.
+    #[serde(default)]
+    pub accept_legacy_hs256: bool,
+    /// OpenID Connect providers users can sign in with, by name.
+    #[serde(default)]
+    pub oidc_providers: HashMap<String, OidcProvider>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SigningKey {
+    /// Identifies the key in the `kid` header of the tokens it signs.
+    pub kid: String,
+    /// Either `RS256` or `EdDSA`.
+    pub algorithm: String,
+    pub public_key_path: String,
+    /// Only needed for the key new tokens are signed with.
+    pub private_key_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProvider {
+    /// Issuer URL, the provider configuration is discovered from
+    /// `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Argon2Params {
+    /// Memory size, in KiB.
+    pub memory_cost: u32,
+    /// Number of iterations.
+    pub time_cost: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordHashing {
+    /// Algorithm new hashes are made with. Hashes made with another algorithm
+    /// or other parameters still verify, and are upgraded on signin.
+    pub algorithm: HashAlgorithm,
+    pub argon2: Argon2Params,
+    pub bcrypt_cost: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// File of SHA-1 hashes of passwords that can't be used, one per line and
+    /// sorted. Lines may be followed by `:<count>` like in the Have I Been
+    /// Pwned downloads ordered by hash.
+    pub banned_list_path: Option<String>,
+    /// Passwords whose normalized Levenshtein similarity to the email or a
+    /// name of the user is above this ratio are rejected.
+    pub max_similarity: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SigninThrottle {
+    /// Failed signins allowed for an account before backing off.
+    pub account_backoff_threshold: u32,
+    /// Failed signins allowed from an IP before backing off.
+    pub ip_backoff_threshold: u32,
+    /// Delay in seconds after reaching a threshold, doubled on each further
+    /// failure.
+    pub backoff_base_delay: i64,
+    /// Upper bound for the backoff delay, in seconds.
+    pub backoff_max_delay: i64,
+    /// Failed signins after which an account gets locked.
+    pub lock_threshold: u32,
+    /// How long failed signins are remembered, in seconds.
+    pub window: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Smtp {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mailer {
+    /// How emails are delivered: `smtp`, `file` or `memory`.
+    pub transport: String,
+    pub from: String,
+    /// Base URL of the client application, used to build links sent by email.
+    pub link_base_url: String,
+    /// Directory where emails are written when using the `file` transport.
+    pub outbox_dir: Option<String>,
+    pub smtp: Option<Smtp>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Checkin {
+    /// How long after its creation a check-in can be edited, in seconds.
+    pub edit_window: i64,
+    /// JSON file with the emotions check-ins can record.
+    pub emotion_wheel_path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountDeletion {
+    /// Seconds between a deletion request and the erasure of the account,
+    /// during which the request can be cancelled.
+    pub grace_period: i64,
+    /// How often accounts due for erasure are looked for, in seconds.
+    pub job_interval: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataExport {
+    /// Directory where export archives are written.
+    pub dir: String,
+    /// How long download links stay valid, in seconds. Archives are removed
+    /// once their link expires.
+    pub download_ttl: i64,
+    /// How often expired archives are looked for, in seconds.
+    pub cleanup_interval: u64,
+    /// How long an export can stay pending, in seconds. Exports pending for
+    /// longer were abandoned, for instance by a restart, and are built again.
+    pub build_timeout: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegacyRoutes {
+    /// Whether the deprecated `/users` routes are served.
+    pub users_enabled: bool,
+    /// RFC 3339 date announced in the `Sunset` header of the `/users` routes.
+    pub users_sunset: String,
 }
 
 // Remove the #[allow(dead_code)] attribute from the Settings struct when all the fields are being
@@ -43,6 +223,14 @@ pub struct Settings {
     pub logger: Logger,
     pub database: Database,
     pub auth: Auth,
+    pub password_hashing: PasswordHashing,
+    pub password_policy: PasswordPolicy,
+    pub mailer: Mailer,
+    pub signin_throttle: SigninThrottle,
+    pub checkin: Checkin,
+    pub account_deletion: AccountDeletion,
+    pub data_export: DataExport,
+    pub legacy_routes: LegacyRoutes,
 }
 
 impl Settings {
@@ -73,4 +261,3 @@ impl fmt::Display for Server {
         write!(f, "http://localhost:{}", &self.port)
     }
 }
-
@@ -1,8 +1,15 @@
 use crate::errors::AuthenticateError;
 use crate::errors::Error;
-use crate::settings::SETTINGS;
+use crate::models::api_token;
+use crate::models::api_token::{ApiToken, Scope};
+use crate::models::revoked_token;
+use crate::models::session;
+use crate::models::user::{Role, User};
+use crate::utils::models::ModelExt;
 use crate::utils::token;
-use crate::utils::token::TokenUser;
+use crate::utils::token::{Claims, TokenUser};
+
+use std::marker::PhantomData;
 
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts, RequestPartsExt};
 
@@ -12,22 +19,200 @@ use axum_extra::{
 };
 
 #[async_trait]
-impl<S> FromRequestParts<S> for TokenUser
+impl<S> FromRequestParts<S> for Claims
 where
     S: Send + Sync,
 {
     type Rejection = Error;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Claims are cached so handlers can extract both `Claims` and
+        // `TokenUser` without validating the token twice. The user loaded
+        // along with them is cached too.
+        if let Some(claims) = parts.extensions.get::<Claims>() {
+            return Ok(claims.clone());
+        }
+
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
             .map_err(|_| AuthenticateError::InvalidToken)?;
 
-        let secret = SETTINGS.auth.secret.as_str();
         let token_data =
-            token::decode(bearer.token(), secret).map_err(|_| AuthenticateError::InvalidToken)?;
+            token::decode(bearer.token()).map_err(|_| AuthenticateError::InvalidToken)?;
+        let claims = token_data.claims;
+
+        if revoked_token::is_revoked(&claims.jti).await? {
+            return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+        }
+
+        if let Some(sid) = &claims.sid {
+            if !session::is_active(sid).await? {
+                return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+            }
+        }
+
+        let user = User::find_by_id(&claims.user.id)
+            .await?
+            .ok_or(AuthenticateError::InvalidToken)?;
+
+        if user.is_token_stale(claims.iat) {
+            return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+        }
+
+        parts.extensions.insert(claims.clone());
+        parts.extensions.insert(user);
+
+        Ok(claims)
+    }
+}
+
+// Personal access tokens are denied by default, routes only accept them
+// through `RequireScope`, which checks the scopes they were granted.
+#[async_trait]
+impl<S> FromRequestParts<S> for TokenUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if api_token::is_api_token(&bearer_token(parts).await?) {
+            return Err(Error::forbidden_with_message(
+                "Personal access tokens are not accepted by this route".to_string(),
+            ));
+        }
+
+        let claims = Claims::from_request_parts(parts, state).await?;
+
+        Ok(claims.user)
+    }
+}
+
+async fn bearer_token(parts: &mut Parts) -> Result<String, Error> {
+    let TypedHeader(Authorization(bearer)) = parts
+        .extract::<TypedHeader<Authorization<Bearer>>>()
+        .await
+        .map_err(|_| AuthenticateError::InvalidToken)?;
+
+    Ok(bearer.token().to_string())
+}
+
+/// Authenticates a personal access token, along with the user it belongs to.
+async fn authenticate_api_token(token: &str) -> Result<(ApiToken, TokenUser), Error> {
+    let api_token = api_token::authenticate(token)
+        .await?
+        .ok_or(AuthenticateError::InvalidToken)?;
+
+    let user = User::find_by_id(&api_token.user)
+        .await?
+        .ok_or(AuthenticateError::InvalidToken)?;
+
+    if user.locked_at.is_some() {
+        return Err(Error::Authenticate(AuthenticateError::Locked));
+    }
+
+    Ok((api_token, TokenUser::from(user)))
+}
+
+/// A role that can be required with `RequireRole`.
+pub trait RoleMarker {
+    const ROLE: Role;
+}
+
+pub struct Admin;
+
+impl RoleMarker for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Extracts the authenticated user, rejecting the request with 403 unless
+/// they have the role `R`. Roles are read from the database, those in the
+/// token are only as recent as the token. Personal access tokens are not
+/// accepted.
+pub struct RequireRole<R: RoleMarker>(pub TokenUser, pub PhantomData<R>);
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    R: RoleMarker,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Claims::from_request_parts(parts, state).await?;
+        let user = parts
+            .extensions
+            .get::<User>()
+            .cloned()
+            .map(TokenUser::from)
+            .ok_or(AuthenticateError::InvalidToken)?;
+
+        if !user.has_role(R::ROLE) {
+            return Err(Error::forbidden_with_message(
+                "Insufficient permissions".to_string(),
+            ));
+        }
+
+        Ok(Self(user, PhantomData))
+    }
+}
+
+/// A personal access token scope that can be required with `RequireScope`.
+pub trait ScopeMarker {
+    const SCOPE: Scope;
+}
+
+pub mod scope {
+    use super::ScopeMarker;
+    use crate::models::api_token::Scope;
+
+    pub struct CheckinsRead;
+    pub struct CheckinsWrite;
+    pub struct MeditationGenerate;
+
+    impl ScopeMarker for CheckinsRead {
+        const SCOPE: Scope = Scope::CheckinsRead;
+    }
+
+    impl ScopeMarker for CheckinsWrite {
+        const SCOPE: Scope = Scope::CheckinsWrite;
+    }
+
+    impl ScopeMarker for MeditationGenerate {
+        const SCOPE: Scope = Scope::MeditationGenerate;
+    }
+}
+
+/// Extracts the authenticated user. Requests authenticated with a personal
+/// access token are rejected with 403 unless the token has the scope `S`,
+/// access tokens obtained by signing in are not restricted.
+pub struct RequireScope<S: ScopeMarker>(pub TokenUser, pub PhantomData<S>);
+
+#[async_trait]
+impl<St, S> FromRequestParts<St> for RequireScope<S>
+where
+    St: Send + Sync,
+    S: ScopeMarker,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &St) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).await?;
+        if !api_token::is_api_token(&token) {
+            let claims = Claims::from_request_parts(parts, state).await?;
+
+            return Ok(Self(claims.user, PhantomData));
+        }
+
+        let (api_token, user) = authenticate_api_token(&token).await?;
+        if !api_token.has_scope(S::SCOPE) {
+            return Err(Error::forbidden_with_message(
+                "Token is missing the required scope".to_string(),
+            ));
+        }
 
-        Ok(token_data.claims.user)
+        Ok(Self(user, PhantomData))
     }
 }
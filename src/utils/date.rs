@@ -1,7 +1,94 @@
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 
 pub type Date = bson::DateTime;
 
 pub fn now() -> Date {
     Utc::now().into()
 }
+
+/// The instant a day starts in the given time zone. Days whose midnight is
+/// skipped by a DST change start at the first hour that exists.
+pub fn start_of_day(tz: Tz, day: NaiveDate) -> DateTime<Utc> {
+    (0..24)
+        .filter_map(|hour| day.and_hms_opt(hour, 0, 0))
+        .find_map(|time| tz.from_local_datetime(&time).earliest())
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| day.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// The instants a calendar month starts and ends in the given time zone.
+pub fn month_range(tz: Tz, year: i32, month: u32) -> Option<(Date, Date)> {
+    let first_day = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next_first_day = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    };
+
+    Some((
+        start_of_day(tz, first_day).into(),
+        start_of_day(tz, next_first_day).into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America::Sao_Paulo, Europe::Paris};
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        assert_eq!(
+            start_of_day(Paris, day(2024, 1, 15)),
+            utc("2024-01-14T23:00:00Z")
+        );
+        assert_eq!(
+            start_of_day(Paris, day(2024, 7, 15)),
+            utc("2024-07-14T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn days_whose_midnight_is_skipped_start_at_the_first_hour() {
+        // São Paulo went from 00:00 to 01:00 when DST started in 2018
+        assert_eq!(
+            start_of_day(Sao_Paulo, day(2018, 11, 4)),
+            utc("2018-11-04T03:00:00Z")
+        );
+        assert_eq!(
+            start_of_day(Sao_Paulo, day(2018, 11, 5)),
+            utc("2018-11-05T02:00:00Z")
+        );
+    }
+
+    #[test]
+    fn months_span_up_to_the_start_of_the_next_one() {
+        let (start, end) = month_range(Paris, 2024, 3).unwrap();
+
+        assert_eq!(start.to_chrono(), utc("2024-02-29T23:00:00Z"));
+        assert_eq!(end.to_chrono(), utc("2024-03-31T22:00:00Z"));
+    }
+
+    #[test]
+    fn december_ends_at_the_start_of_the_next_year() {
+        let (start, end) = month_range(Paris, 2024, 12).unwrap();
+
+        assert_eq!(start.to_chrono(), utc("2024-11-30T23:00:00Z"));
+        assert_eq!(end.to_chrono(), utc("2024-12-31T23:00:00Z"));
+    }
+
+    #[test]
+    fn invalid_months_have_no_range() {
+        assert!(month_range(Paris, 2024, 0).is_none());
+        assert!(month_range(Paris, 2024, 13).is_none());
+    }
+}
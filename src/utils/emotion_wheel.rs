@@ -0,0 +1,260 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use tracing::info;
+
+use crate::settings::SETTINGS;
+
+/// Core, secondary and tertiary emotions.
+const MAX_DEPTH: usize = 3;
+
+static WHEEL: Lazy<EmotionWheel> = Lazy::new(|| {
+    let path = &SETTINGS.checkin.emotion_wheel_path;
+    let content = fs::read_to_string(path).expect("Failed to read the emotion wheel");
+    let wheel =
+        serde_json::from_str::<EmotionWheel>(&content).expect("Failed to parse the emotion wheel");
+
+    let wheel = wheel.indexed();
+    info!("Loaded {} emotions", wheel.index.len());
+
+    wheel
+});
+
+// The emotions a check-in can record, as a tree whose roots are the core
+// emotions. Ids are unique across the whole tree and are what check-ins
+// store, labels are only used for display and can be changed freely.
+#[derive(Debug, Deserialize)]
+pub struct EmotionWheel {
+    pub default_locale: String,
+    pub emotions: Vec<Emotion>,
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Emotion {
+    pub id: String,
+    /// Labels by locale, the default locale is required.
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub children: Vec<Emotion>,
+}
+
+/// An emotion with its label in a single locale.
+#[derive(Debug, Serialize)]
+pub struct LocalizedEmotion {
+    pub id: String,
+    pub label: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<LocalizedEmotion>,
+}
+
+impl EmotionWheel {
+    // Records the depth of every emotion. Mistakes in the file are panics, the
+    // wheel is loaded when the app is created so they stop the server early
+    fn indexed(mut self) -> Self {
+        fn walk(
+            emotions: &[Emotion],
+            depth: usize,
+            default_locale: &str,
+            index: &mut HashMap<String, usize>,
+        ) {
+            for emotion in emotions {
+                assert!(depth <= MAX_DEPTH, "The emotion wheel is too deep");
+                assert!(
+                    emotion.labels.contains_key(default_locale),
+                    "Emotion {} has no {} label",
+                    emotion.id,
+                    default_locale
+                );
+                let previous = index.insert(emotion.id.clone(), depth);
+                assert!(previous.is_none(), "Emotion {} is duplicated", emotion.id);
+
+                walk(&emotion.children, depth + 1, default_locale, index);
+            }
+        }
+
+        let mut index = HashMap::new();
+        walk(&self.emotions, 1, &self.default_locale, &mut index);
+        self.index = index;
+
+        self
+    }
+
+    /// The most specific locale labels exist for, `pt-BR` falls back to `pt`
+    /// and then to the default locale.
+    pub fn resolve_locale(&self, locale: &str) -> String {
+        let locale = locale.trim();
+        let available = |candidate: &str| {
+            self.emotions
+                .iter()
+                .any(|emotion| emotion.labels.contains_key(candidate))
+        };
+
+        if !locale.is_empty() && available(locale) {
+            return locale.to_string();
+        }
+
+        let language = locale.split('-').next().unwrap_or_default();
+        if !language.is_empty() && available(language) {
+            return language.to_string();
+        }
+
+        self.default_locale.clone()
+    }
+
+    pub fn localized(&self, locale: &str) -> Vec<LocalizedEmotion> {
+        fn localize(emotion: &Emotion, locale: &str, default_locale: &str) -> LocalizedEmotion {
+            let label = emotion
+                .labels
+                .get(locale)
+                .or_else(|| emotion.labels.get(default_locale))
+                .cloned()
+                .unwrap_or_default();
+
+            LocalizedEmotion {
+                id: emotion.id.clone(),
+                label,
+                children: emotion
+                    .children
+                    .iter()
+                    .map(|child| localize(child, locale, default_locale))
+                    .collect(),
+            }
+        }
+
+        self.emotions
+            .iter()
+            .map(|emotion| localize(emotion, locale, &self.default_locale))
+            .collect()
+    }
+}
+
+pub fn wheel() -> &'static EmotionWheel {
+    &WHEEL
+}
+
+/// Whether the emotion is one of the roots of the wheel.
+pub fn is_core(id: &str) -> bool {
+    WHEEL.index.get(id) == Some(&1)
+}
+
+pub fn is_known(id: &str) -> bool {
+    WHEEL.index.contains_key(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn wheel_from(value: serde_json::Value) -> EmotionWheel {
+        serde_json::from_value::<EmotionWheel>(value)
+            .unwrap()
+            .indexed()
+    }
+
+    fn sample_wheel() -> EmotionWheel {
+        wheel_from(json!({
+            "default_locale": "en",
+            "emotions": [{
+                "id": "joy",
+                "labels": { "en": "Joy", "pt": "Alegria", "pt-PT": "Alegria" },
+                "children": [{
+                    "id": "content",
+                    "labels": { "en": "Content" },
+                    "children": [{ "id": "pleased", "labels": { "en": "Pleased" } }]
+                }]
+            }]
+        }))
+    }
+
+    #[test]
+    fn locales_with_labels_are_kept() {
+        let wheel = sample_wheel();
+
+        assert_eq!(wheel.resolve_locale("pt-PT"), "pt-PT");
+        assert_eq!(wheel.resolve_locale(" pt "), "pt");
+        assert_eq!(wheel.resolve_locale("en"), "en");
+    }
+
+    #[test]
+    fn regional_locales_fall_back_to_their_language() {
+        assert_eq!(sample_wheel().resolve_locale("pt-BR"), "pt");
+    }
+
+    #[test]
+    fn unknown_locales_fall_back_to_the_default() {
+        let wheel = sample_wheel();
+
+        assert_eq!(wheel.resolve_locale("fr-FR"), "en");
+        assert_eq!(wheel.resolve_locale(""), "en");
+        assert_eq!(wheel.resolve_locale("-BR"), "en");
+    }
+
+    #[test]
+    fn missing_labels_use_the_default_locale() {
+        let emotions = sample_wheel().localized("pt");
+
+        assert_eq!(emotions[0].label, "Alegria");
+        assert_eq!(emotions[0].children[0].label, "Content");
+    }
+
+    #[test]
+    fn depths_are_indexed() {
+        let wheel = sample_wheel();
+
+        assert_eq!(wheel.index.get("joy"), Some(&1));
+        assert_eq!(wheel.index.get("content"), Some(&2));
+        assert_eq!(wheel.index.get("pleased"), Some(&3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Emotion joy is duplicated")]
+    fn duplicate_ids_are_rejected() {
+        wheel_from(json!({
+            "default_locale": "en",
+            "emotions": [{
+                "id": "joy",
+                "labels": { "en": "Joy" },
+                "children": [{ "id": "joy", "labels": { "en": "Joy" } }]
+            }]
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Emotion joy has no en label")]
+    fn default_labels_are_required() {
+        wheel_from(json!({
+            "default_locale": "en",
+            "emotions": [{ "id": "joy", "labels": { "pt": "Alegria" } }]
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "The emotion wheel is too deep")]
+    fn deep_wheels_are_rejected() {
+        wheel_from(json!({
+            "default_locale": "en",
+            "emotions": [{
+                "id": "joy",
+                "labels": { "en": "Joy" },
+                "children": [{
+                    "id": "content",
+                    "labels": { "en": "Content" },
+                    "children": [{
+                        "id": "pleased",
+                        "labels": { "en": "Pleased" },
+                        "children": [{ "id": "glad", "labels": { "en": "Glad" } }]
+                    }]
+                }]
+            }]
+        }));
+    }
+
+    #[test]
+    fn configured_wheel_is_valid() {
+        assert!(!wheel().emotions.is_empty());
+    }
+}
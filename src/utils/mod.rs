@@ -1,7 +1,17 @@
 pub mod authenticate_request;
+pub mod client_ip;
 pub mod custom_response;
 pub mod date;
+pub mod emotion_wheel;
 pub mod models;
+pub mod oidc;
+pub mod opaque_token;
 pub mod pagination;
+pub mod password;
+pub mod password_policy;
+pub mod request_context;
+pub mod signing_keys;
 pub mod to_object_id;
 pub mod token;
+pub mod totp;
+pub mod user_agent;
@@ -109,6 +109,13 @@ where
             .map_err(Error::Wither)
     }
 
+    async fn find_one_and_delete(query: Document) -> Result<Option<Self>, Error> {
+        let connection = database::connection().await;
+        <Self as WitherModel>::find_one_and_delete(connection, query, None)
+            .await
+            .map_err(Error::Wither)
+    }
+
     async fn update_one<O>(
         query: Document,
         update: Document,
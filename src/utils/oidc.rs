@@ -0,0 +1,232 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use crate::errors::{AuthenticateError, Error};
+use crate::settings::{OidcProvider, SETTINGS};
+
+// Provider metadata and keys are fetched once and kept in memory. Keys are
+// fetched again when an ID token is signed with an unknown key, which is
+// what happens after the provider rotates them.
+static PROVIDERS: Lazy<RwLock<HashMap<String, Provider>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Clone)]
+struct Provider {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    // Some providers send this claim as a string
+    #[serde(default, deserialize_with = "deserialize_bool_or_string")]
+    pub email_verified: bool,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub name: Option<String>,
+}
+
+fn deserialize_bool_or_string<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+
+    Ok(match BoolOrString::deserialize(deserializer)? {
+        BoolOrString::Bool(value) => value,
+        BoolOrString::String(value) => value == "true",
+    })
+}
+
+pub fn provider_settings(name: &str) -> Result<&'static OidcProvider, Error> {
+    SETTINGS
+        .auth
+        .oidc_providers
+        .get(name)
+        .ok_or_else(Error::not_found)
+}
+
+/// Derives the PKCE code challenge of a code verifier, using the S256 method.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Builds the URL users are sent to in order to authenticate with the
+/// provider.
+pub async fn authorization_url(
+    name: &str,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+) -> Result<String, Error> {
+    let settings = provider_settings(name)?;
+    let provider = provider(name, false).await?;
+
+    let url = Url::parse_with_params(
+        &provider.metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", settings.client_id.as_str()),
+            ("redirect_uri", settings.redirect_uri.as_str()),
+            ("scope", settings.scopes.join(" ").as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge(code_verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| Error::IdentityProvider(e.to_string()))?;
+
+    Ok(url.to_string())
+}
+
+/// Exchanges an authorization code for an ID token, and returns its claims
+/// once validated.
+pub async fn exchange_code(
+    name: &str,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims, Error> {
+    let settings = provider_settings(name)?;
+    let provider = provider(name, false).await?;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", settings.redirect_uri.as_str()),
+        ("client_id", settings.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(client_secret) = &settings.client_secret {
+        form.push(("client_secret", client_secret.as_str()));
+    }
+
+    let response = reqwest::Client::new()
+        .post(&provider.metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| Error::IdentityProvider(e.to_string()))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(Error::IdentityProvider(format!(
+            "Token endpoint returned error: {}",
+            error_text
+        )));
+    }
+
+    let token_response = response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| Error::IdentityProvider(e.to_string()))?;
+
+    let claims = validate_id_token(name, &token_response.id_token).await?;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+    }
+
+    Ok(claims)
+}
+
+async fn validate_id_token(name: &str, id_token: &str) -> Result<IdTokenClaims, Error> {
+    let settings = provider_settings(name)?;
+    let header =
+        jsonwebtoken::decode_header(id_token).map_err(|_| AuthenticateError::InvalidToken)?;
+
+    // Only asymmetric algorithms are accepted, an ID token signed with the
+    // client secret would not prove anything
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+    }
+
+    let kid = header.kid.ok_or(AuthenticateError::InvalidToken)?;
+    let mut provider = provider(name, false).await?;
+    if provider.jwks.find(&kid).is_none() {
+        provider = self::provider(name, true).await?;
+    }
+
+    let jwk = provider
+        .jwks
+        .find(&kid)
+        .ok_or(AuthenticateError::InvalidToken)?;
+    let decoding_key = DecodingKey::from_jwk(jwk).map_err(|_| AuthenticateError::InvalidToken)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&provider.metadata.issuer]);
+    validation.set_audience(&[&settings.client_id]);
+
+    let token_data = jsonwebtoken::decode::<IdTokenClaims>(id_token, &decoding_key, &validation)
+        .map_err(|_| AuthenticateError::InvalidToken)?;
+
+    Ok(token_data.claims)
+}
+
+async fn provider(name: &str, refresh: bool) -> Result<Provider, Error> {
+    if !refresh {
+        if let Some(provider) = PROVIDERS.read().await.get(name) {
+            return Ok(provider.clone());
+        }
+    }
+
+    let settings = provider_settings(name)?;
+    let discovery_url = format!(
+        "{}/.well-known/openid-configuration",
+        settings.issuer.trim_end_matches('/')
+    );
+
+    let metadata = fetch_json::<ProviderMetadata>(&discovery_url).await?;
+    let jwks = fetch_json::<JwkSet>(&metadata.jwks_uri).await?;
+    let provider = Provider { metadata, jwks };
+
+    PROVIDERS
+        .write()
+        .await
+        .insert(name.to_string(), provider.clone());
+
+    Ok(provider)
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, Error> {
+    reqwest::get(url)
+        .await
+        .map_err(|e| Error::IdentityProvider(e.to_string()))?
+        .error_for_status()
+        .map_err(|e| Error::IdentityProvider(e.to_string()))?
+        .json::<T>()
+        .await
+        .map_err(|e| Error::IdentityProvider(e.to_string()))
+}
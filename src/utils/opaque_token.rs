@@ -0,0 +1,16 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Generates a random, URL safe token. These tokens carry no information by
+/// themselves and are only meaningful when looked up by their hash.
+pub fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a token so it can be stored and looked up without persisting the
+/// token itself.
+pub fn hash<T: AsRef<str>>(token: T) -> String {
+    hex::encode(Sha256::digest(token.as_ref().as_bytes()))
+}
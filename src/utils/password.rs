@@ -0,0 +1,162 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{
+    self, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bcrypt::HashParts;
+use once_cell::sync::Lazy;
+use tokio::task;
+
+use crate::errors::Error;
+use crate::settings::{HashAlgorithm, SETTINGS};
+
+static ARGON2ID: Lazy<Argon2id> = Lazy::new(|| {
+    let argon2 = &SETTINGS.password_hashing.argon2;
+    Argon2id::new(argon2.memory_cost, argon2.time_cost, argon2.parallelism)
+});
+
+static BCRYPT: Lazy<Bcrypt> = Lazy::new(|| Bcrypt {
+    cost: SETTINGS.password_hashing.bcrypt_cost,
+});
+
+/// The algorithm new hashes are made with.
+fn configured() -> &'static dyn Hasher {
+    match SETTINGS.password_hashing.algorithm {
+        HashAlgorithm::Argon2id => &*ARGON2ID,
+        HashAlgorithm::Bcrypt => &*BCRYPT,
+    }
+}
+
+/// The algorithm a stored hash was made with.
+fn hasher_of(hash: &str) -> Option<&'static dyn Hasher> {
+    [&*ARGON2ID as &'static dyn Hasher, &*BCRYPT]
+        .into_iter()
+        .find(|hasher| hasher.recognizes(hash))
+}
+
+// Every algorithm a stored hash may use is supported for verification,
+// whichever is configured for new hashes. Hashes are recognized by their
+// PHC / modular crypt prefix.
+trait Hasher: Send + Sync {
+    fn hash(&self, password: &str) -> Result<String, Error>;
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, Error>;
+
+    /// Whether the hash was made by this algorithm.
+    fn recognizes(&self, hash: &str) -> bool;
+
+    /// Whether the hash was made by this algorithm with its current
+    /// parameters.
+    fn is_current(&self, hash: &str) -> bool;
+}
+
+struct Argon2id {
+    params: Params,
+}
+
+impl Argon2id {
+    fn new(memory_cost: u32, time_cost: u32, parallelism: u32) -> Self {
+        let params = Params::new(memory_cost, time_cost, parallelism, None)
+            .expect("Invalid argon2 parameters");
+
+        Self { params }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl Hasher for Argon2id {
+    fn hash(&self, password: &str) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2().hash_password(password.as_bytes(), &salt)?;
+
+        Ok(hash.to_string())
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, Error> {
+        let hash = PasswordHash::new(hash)
+            .map_err(|_| Error::InvalidPassword("Invalid password".to_string()))?;
+
+        // The parameters are read from the hash, so older hashes still verify
+        match Argon2::default().verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Ok(true),
+            Err(password_hash::Error::Password) => Ok(false),
+            Err(err) => Err(Error::PasswordHash(err)),
+        }
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2")
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        let hash = match PasswordHash::new(hash) {
+            Ok(hash) => hash,
+            Err(_) => return false,
+        };
+
+        hash.algorithm == Algorithm::Argon2id.ident()
+            && hash.version == Some(Version::V0x13.into())
+            && Params::try_from(&hash).is_ok_and(|params| {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            })
+    }
+}
+
+struct Bcrypt {
+    cost: u32,
+}
+
+impl Hasher for Bcrypt {
+    fn hash(&self, password: &str) -> Result<String, Error> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, Error> {
+        bcrypt::verify(password, hash)
+            .map_err(|_| Error::InvalidPassword("Invalid password".to_string()))
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$2")
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        hash.parse::<HashParts>()
+            .is_ok_and(|parts| parts.get_cost() == self.cost)
+    }
+}
+
+/// Hashes a password with the configured algorithm.
+pub async fn hash<P>(password: P) -> Result<String, Error>
+where
+    P: AsRef<str> + Send + 'static,
+{
+    task::spawn_blocking(move || configured().hash(password.as_ref()))
+        .await
+        .map_err(Error::RunSyncTask)?
+}
+
+/// Verifies a password against a hash made by any supported algorithm.
+pub async fn verify<P, H>(password: P, hash: H) -> Result<bool, Error>
+where
+    P: AsRef<str> + Send + 'static,
+    H: AsRef<str> + Send + 'static,
+{
+    task::spawn_blocking(move || match hasher_of(hash.as_ref()) {
+        Some(hasher) => hasher.verify(password.as_ref(), hash.as_ref()),
+        None => Err(Error::InvalidPassword("Invalid password".to_string())),
+    })
+    .await
+    .map_err(Error::RunSyncTask)?
+}
+
+/// Whether the hash should be replaced, because it was made with another
+/// algorithm or outdated parameters.
+pub fn needs_rehash(hash: &str) -> bool {
+    !configured().is_current(hash)
+}
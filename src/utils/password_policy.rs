@@ -0,0 +1,327 @@
+use once_cell::sync::Lazy;
+use sha1::{Digest, Sha1};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use tracing::info;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::errors::Error;
+use crate::settings::SETTINGS;
+
+/// Personal values shorter than this are too common to be checked against,
+/// a user named Ann can still use "planned".
+const MIN_PERSONAL_VALUE_LENGTH: usize = 4;
+
+/// Length of a SHA-1 hash in hex.
+const HASH_LENGTH: usize = 40;
+
+/// How much of the banned list is read at once while looking for a line.
+const READ_CHUNK_SIZE: usize = 128;
+
+static BANNED_LIST: Lazy<Option<BannedList>> = Lazy::new(|| {
+    let path = SETTINGS.password_policy.banned_list_path.as_ref()?;
+    let list = BannedList::open(path).expect("Failed to read the banned password list");
+    info!("Opened banned password list of {} bytes", list.len);
+
+    Some(list)
+});
+
+// SHA-1 hashes of banned passwords, sorted. The file has one hash per line,
+// optionally followed by `:<count>`, which is the format of the Have I Been
+// Pwned downloads ordered by hash so they can be used as is. Those are tens of
+// gigabytes, so the file is never loaded, lookups are a binary search over it
+// that reads a few dozen lines.
+struct BannedList {
+    file: File,
+    len: u64,
+}
+
+impl BannedList {
+    // Only the first line is checked, which catches a wrong path or format
+    // early. Whether the whole file is sorted can't be checked without
+    // reading it.
+    fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let list = Self { file, len };
+
+        let first = list.hash_at(0)?.unwrap_or_default();
+        if !is_hash(&first) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The list must have one SHA-1 hash per line",
+            ));
+        }
+
+        Ok(list)
+    }
+
+    fn contains(&self, hash: &str) -> io::Result<bool> {
+        // Finds the first offset whose next line is at or after the hash
+        let mut low = 0;
+        let mut high = self.len;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.hash_at(middle)? {
+                Some(found) if found.as_str() < hash => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+
+        Ok(self.hash_at(low)?.as_deref() == Some(hash))
+    }
+
+    /// The hash of the first line starting at or after the offset.
+    fn hash_at(&self, offset: u64) -> io::Result<Option<String>> {
+        let start = match offset {
+            0 => 0,
+            // Skips the rest of the line the offset is in
+            _ => offset - 1 + self.read_line(offset - 1)?.len() as u64,
+        };
+        if start >= self.len {
+            return Ok(None);
+        }
+
+        let line = self.read_line(start)?;
+        let line = String::from_utf8_lossy(&line);
+        let hash = line.split(':').next().unwrap_or_default();
+
+        Ok(Some(hash.trim().to_uppercase()))
+    }
+
+    /// The bytes from the position up to and including the next newline.
+    fn read_line(&self, mut position: u64) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        let mut chunk = [0; READ_CHUNK_SIZE];
+
+        while position < self.len {
+            let read = self.file.read_at(&mut chunk, position)?;
+            if read == 0 {
+                break;
+            }
+
+            if let Some(newline) = chunk[..read].iter().position(|byte| *byte == b'\n') {
+                line.extend_from_slice(&chunk[..=newline]);
+                break;
+            }
+            line.extend_from_slice(&chunk[..read]);
+            position += read as u64;
+        }
+
+        Ok(line)
+    }
+}
+
+fn is_hash(value: &str) -> bool {
+    value.len() == HASH_LENGTH && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Opens the banned password list now, so a broken one stops the server
+/// instead of the first signup.
+pub fn load() {
+    Lazy::force(&BANNED_LIST);
+}
+
+/// Checks a password against the configured policy. `personal_values` are the
+/// email and names of the user, the password shouldn't be based on them.
+/// Violations are reported as errors of the given field.
+pub async fn check(
+    field: &'static str,
+    password: &str,
+    personal_values: &[&str],
+) -> Result<(), Error> {
+    let policy = &SETTINGS.password_policy;
+    let mut errors = ValidationErrors::new();
+
+    if password.chars().count() < policy.min_length {
+        let mut error = violation(
+            "too_short",
+            format!("Must be at least {} characters long", policy.min_length),
+        );
+        error.add_param(Cow::from("min"), &policy.min_length);
+        errors.add(field, error);
+    }
+
+    if is_banned(password).await? {
+        errors.add(
+            field,
+            violation(
+                "banned",
+                "Is too common or appeared in a data breach".to_string(),
+            ),
+        );
+    }
+
+    if is_similar_to_any(password, personal_values, policy.max_similarity) {
+        errors.add(
+            field,
+            violation(
+                "too_similar",
+                "Is too similar to your email or name".to_string(),
+            ),
+        );
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::Validation(errors))
+}
+
+fn violation(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+async fn is_banned(password: &str) -> Result<bool, Error> {
+    let list = match BANNED_LIST.as_ref() {
+        Some(list) => list,
+        None => return Ok(false),
+    };
+
+    // The lookup reads from the file, which would hold up a runtime thread
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let banned = tokio::task::spawn_blocking(move || list.contains(&hash)).await??;
+
+    Ok(banned)
+}
+
+fn is_similar_to_any(password: &str, personal_values: &[&str], max_similarity: f64) -> bool {
+    let password = password.to_lowercase();
+
+    personal_values
+        .iter()
+        .flat_map(|value| personal_variants(value))
+        .filter(|value| value.chars().count() >= MIN_PERSONAL_VALUE_LENGTH)
+        .any(|value| {
+            password.contains(&value)
+                || strsim::normalized_levenshtein(&password, &value) > max_similarity
+        })
+}
+
+// An email is also checked without its domain, `jane.doe@example.com` makes
+// `jane.doe2024` as guessable as the full address does.
+fn personal_variants(value: &str) -> Vec<String> {
+    let value = value.trim().to_lowercase();
+
+    match value.split_once('@') {
+        Some((local_part, _)) => vec![local_part.to_string(), value.clone()],
+        None => vec![value],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sha1(password: &str) -> String {
+        hex::encode_upper(Sha1::digest(password.as_bytes()))
+    }
+
+    // Writes a list to a file of its own, tests run in parallel
+    fn banned_list_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "banned-passwords-{}-{}.txt",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn open_banned_list(name: &str, content: &str) -> io::Result<BannedList> {
+        let path = banned_list_file(name, content);
+        let list = BannedList::open(path.to_str().unwrap());
+        std::fs::remove_file(path).unwrap();
+        list
+    }
+
+    fn sorted_hashes(passwords: &[&str]) -> Vec<String> {
+        let mut hashes: Vec<String> = passwords.iter().map(|p| sha1(p)).collect();
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn banned_list_finds_every_hash() {
+        let passwords = ["123456", "password", "qwerty", "letmein", "dragon"];
+        let content = sorted_hashes(&passwords)
+            .iter()
+            .enumerate()
+            .map(|(count, hash)| format!("{hash}:{count}\n"))
+            .collect::<String>();
+        let list = open_banned_list("every", &content).unwrap();
+
+        for password in passwords {
+            assert!(list.contains(&sha1(password)).unwrap(), "{password}");
+        }
+        assert!(!list.contains(&sha1("correct horse battery")).unwrap());
+        assert!(!list.contains(&"0".repeat(HASH_LENGTH)).unwrap());
+        assert!(!list.contains(&"F".repeat(HASH_LENGTH)).unwrap());
+    }
+
+    #[test]
+    fn banned_list_reads_the_last_line_without_newline() {
+        let hashes = sorted_hashes(&["password", "qwerty"]);
+        let list = open_banned_list("no-newline", &hashes.join("\n")).unwrap();
+
+        assert!(list.contains(&hashes[0]).unwrap());
+        assert!(list.contains(&hashes[1]).unwrap());
+    }
+
+    #[test]
+    fn banned_list_accepts_lowercase_hashes() {
+        let hash = sha1("password");
+        let list = open_banned_list("lowercase", &format!("{}\n", hash.to_lowercase())).unwrap();
+
+        assert!(list.contains(&hash).unwrap());
+    }
+
+    #[test]
+    fn banned_list_must_start_with_a_hash() {
+        let error = open_banned_list("plain", "password\n123456\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = open_banned_list("empty", "").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn emails_are_checked_with_and_without_their_domain() {
+        assert_eq!(
+            personal_variants(" Jane.Doe@Example.com "),
+            vec!["jane.doe", "jane.doe@example.com"]
+        );
+        assert_eq!(personal_variants("Jane"), vec!["jane"]);
+    }
+
+    #[test]
+    fn passwords_containing_personal_values_are_similar() {
+        let values = ["jane.doe@example.com", "Jane"];
+
+        assert!(is_similar_to_any("Jane.Doe2024", &values, 0.7));
+        assert!(is_similar_to_any("ilovejane!", &values, 0.7));
+        assert!(!is_similar_to_any("correct horse battery", &values, 0.7));
+    }
+
+    #[test]
+    fn passwords_close_to_personal_values_are_similar() {
+        let values = ["Johnathan"];
+
+        assert!(is_similar_to_any("jonathan", &values, 0.7));
+        assert!(!is_similar_to_any("jonathan", &values, 0.9));
+    }
+
+    #[test]
+    fn short_personal_values_are_ignored() {
+        assert!(!is_similar_to_any("planned", &["Ann"], 0.7));
+        assert!(!is_similar_to_any("planned", &["", " "], 0.7));
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use std::convert::Infallible;
+use std::net::IpAddr;
+
+use crate::utils::client_ip::ClientIp;
+use crate::utils::user_agent::UserAgent;
+
+/// Where a request comes from, recorded along with audit events. Requests get
+/// an `X-Request-Id` in `app.rs` when the client didn't send one.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        let UserAgent(user_agent) = UserAgent::from_request_parts(parts, state).await?;
+
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(128).collect::<String>());
+
+        Ok(Self {
+            ip,
+            user_agent,
+            request_id,
+        })
+    }
+}
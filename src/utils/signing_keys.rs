@@ -0,0 +1,308 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use once_cell::sync::Lazy;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPublicKey;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::errors::Error;
+use crate::settings::{Auth, SETTINGS};
+
+// Tokens are signed with the key selected by `auth.signing_kid`, and
+// verified with the key matching their `kid` header. Keeping the previous
+// key configured after switching to a new one lets tokens it signed expire
+// naturally instead of logging everyone out. Tokens without `kid` were
+// signed with the shared secret, they are only accepted while no `signing_kid`
+// is set or when `auth.accept_legacy_hs256` is on.
+static KEYS: Lazy<KeyStore> =
+    Lazy::new(|| KeyStore::load(&SETTINGS.auth).expect("Failed to load JWT signing keys"));
+
+static LEGACY_VALIDATION: Lazy<Validation> = Lazy::new(Validation::default);
+
+struct VerificationKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    jwk: Value,
+}
+
+struct KeyStore {
+    header: Header,
+    encoding_key: EncodingKey,
+    keys: HashMap<String, VerificationKey>,
+    legacy_key: Option<DecodingKey>,
+}
+
+impl KeyStore {
+    fn load(settings: &Auth) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for key in &settings.signing_keys {
+            let algorithm = parse_algorithm(&key.algorithm)?;
+            let pem = read_pem(&key.public_key_path)?;
+            let decoding_key = match algorithm {
+                Algorithm::RS256 => DecodingKey::from_rsa_pem(&pem),
+                _ => DecodingKey::from_ed_pem(&pem),
+            }
+            .map_err(|e| format!("Invalid public key {}: {}", key.kid, e))?;
+            let jwk = match algorithm {
+                Algorithm::RS256 => rsa_jwk(&key.kid, &pem)?,
+                _ => ed25519_jwk(&key.kid, &pem)?,
+            };
+
+            keys.insert(
+                key.kid.clone(),
+                VerificationKey {
+                    algorithm,
+                    decoding_key,
+                    jwk,
+                },
+            );
+        }
+
+        let (header, encoding_key) = match &settings.signing_kid {
+            Some(kid) => {
+                let key = settings
+                    .signing_keys
+                    .iter()
+                    .find(|key| &key.kid == kid)
+                    .ok_or_else(|| format!("Unknown signing key {kid}"))?;
+                let path = key
+                    .private_key_path
+                    .as_ref()
+                    .ok_or_else(|| format!("Missing private key for signing key {kid}"))?;
+
+                let algorithm = parse_algorithm(&key.algorithm)?;
+                let pem = read_pem(path)?;
+                let encoding_key = match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(&pem),
+                    _ => EncodingKey::from_ed_pem(&pem),
+                }
+                .map_err(|e| format!("Invalid private key {kid}: {e}"))?;
+
+                let mut header = Header::new(algorithm);
+                header.kid = Some(kid.clone());
+                (header, encoding_key)
+            }
+            None => (
+                Header::default(),
+                EncodingKey::from_secret(settings.secret.as_ref()),
+            ),
+        };
+
+        let legacy_key = (settings.signing_kid.is_none() || settings.accept_legacy_hs256)
+            .then(|| DecodingKey::from_secret(settings.secret.as_ref()));
+
+        Ok(Self {
+            header,
+            encoding_key,
+            keys,
+            legacy_key,
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, JwtError> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let kid = match (header.kid, &self.legacy_key) {
+            (Some(kid), _) => kid,
+            (None, Some(legacy_key)) => {
+                return jsonwebtoken::decode::<T>(token, legacy_key, &LEGACY_VALIDATION)
+            }
+            (None, None) => return Err(ErrorKind::InvalidAlgorithm.into()),
+        };
+
+        let key = self.keys.get(&kid).ok_or(ErrorKind::InvalidKeyFormat)?;
+        if header.alg != key.algorithm {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        jsonwebtoken::decode::<T>(token, &key.decoding_key, &Validation::new(key.algorithm))
+    }
+}
+
+fn parse_algorithm(algorithm: &str) -> Result<Algorithm, String> {
+    match algorithm {
+        "RS256" => Ok(Algorithm::RS256),
+        "EdDSA" => Ok(Algorithm::EdDSA),
+        algorithm => Err(format!("Unsupported signing algorithm {algorithm}")),
+    }
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read key {path}: {e}"))
+}
+
+fn rsa_jwk(kid: &str, pem: &[u8]) -> Result<Value, String> {
+    let pem = std::str::from_utf8(pem).map_err(|e| e.to_string())?;
+    let key = RsaPublicKey::from_public_key_pem(pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+        .map_err(|e| format!("Invalid public key {kid}: {e}"))?;
+
+    Ok(json!({
+        "kty": "RSA",
+        "use": "sig",
+        "alg": "RS256",
+        "kid": kid,
+        "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+    }))
+}
+
+fn ed25519_jwk(kid: &str, pem: &[u8]) -> Result<Value, String> {
+    let pem = pem::parse(pem).map_err(|e| format!("Invalid public key {kid}: {e}"))?;
+
+    // Ed25519 public keys are encoded as a fixed 12 bytes prefix followed by
+    // the 32 bytes of the key
+    let der = pem.contents();
+    if der.len() != 44 {
+        return Err(format!("Invalid Ed25519 public key {kid}"));
+    }
+
+    Ok(json!({
+        "kty": "OKP",
+        "crv": "Ed25519",
+        "use": "sig",
+        "alg": "EdDSA",
+        "kid": kid,
+        "x": URL_SAFE_NO_PAD.encode(&der[12..]),
+    }))
+}
+
+/// Loads the keys, panicking on a missing or invalid one. Called when the app
+/// is created so a bad key stops the server instead of the first request.
+pub fn load() {
+    Lazy::force(&KEYS);
+}
+
+pub fn encode<T: Serialize>(claims: &T) -> Result<String, Error> {
+    jsonwebtoken::encode(&KEYS.header, claims, &KEYS.encoding_key)
+        .map_err(|e| Error::TokenCreation(e.to_string()))
+}
+
+pub fn decode<T: DeserializeOwned>(token: &str) -> Result<TokenData<T>, JwtError> {
+    KEYS.decode(token)
+}
+
+/// The public keys tokens can be verified with, as a JSON Web Key Set.
+pub fn jwks() -> Value {
+    let keys = KEYS
+        .keys
+        .values()
+        .map(|key| key.jwk.clone())
+        .collect::<Vec<Value>>();
+
+    json!({ "keys": keys })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_SECRET: &[u8] = b"legacy secret";
+    const KEY_SECRET: &[u8] = b"key secret";
+
+    // Shared secret keys stand in for the RS256 and EdDSA ones, the key
+    // selection doesn't depend on the algorithm
+    fn key_store(accept_legacy: bool) -> KeyStore {
+        let mut keys = HashMap::new();
+        keys.insert(
+            "current".to_string(),
+            VerificationKey {
+                algorithm: Algorithm::HS384,
+                decoding_key: DecodingKey::from_secret(KEY_SECRET),
+                jwk: Value::Null,
+            },
+        );
+
+        KeyStore {
+            header: Header::new(Algorithm::HS384),
+            encoding_key: EncodingKey::from_secret(KEY_SECRET),
+            keys,
+            legacy_key: accept_legacy.then(|| DecodingKey::from_secret(LEGACY_SECRET)),
+        }
+    }
+
+    fn token(algorithm: Algorithm, kid: Option<&str>, secret: &[u8]) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = kid.map(str::to_string);
+        let claims = json!({ "sub": "jane", "exp": 4_102_444_800_u64 });
+
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn error_kind(result: Result<TokenData<Value>, JwtError>) -> ErrorKind {
+        result.unwrap_err().into_kind()
+    }
+
+    #[test]
+    fn tokens_are_verified_with_the_key_of_their_kid() {
+        let keys = key_store(false);
+        let token = token(Algorithm::HS384, Some("current"), KEY_SECRET);
+
+        assert!(keys.decode::<Value>(&token).is_ok());
+    }
+
+    #[test]
+    fn unknown_kids_are_rejected() {
+        let keys = key_store(true);
+        let token = token(Algorithm::HS384, Some("retired"), KEY_SECRET);
+
+        assert_eq!(
+            error_kind(keys.decode::<Value>(&token)),
+            ErrorKind::InvalidKeyFormat
+        );
+    }
+
+    #[test]
+    fn algorithms_other_than_the_one_of_the_key_are_rejected() {
+        let keys = key_store(true);
+        let token = token(Algorithm::HS256, Some("current"), KEY_SECRET);
+
+        assert_eq!(
+            error_kind(keys.decode::<Value>(&token)),
+            ErrorKind::InvalidAlgorithm
+        );
+    }
+
+    #[test]
+    fn tokens_without_kid_use_the_legacy_secret_when_accepted() {
+        let keys = key_store(true);
+        let token = token(Algorithm::HS256, None, LEGACY_SECRET);
+
+        assert!(keys.decode::<Value>(&token).is_ok());
+    }
+
+    #[test]
+    fn tokens_without_kid_are_rejected_without_the_legacy_secret() {
+        let keys = key_store(false);
+        let token = token(Algorithm::HS256, None, LEGACY_SECRET);
+
+        assert_eq!(
+            error_kind(keys.decode::<Value>(&token)),
+            ErrorKind::InvalidAlgorithm
+        );
+    }
+
+    #[test]
+    fn legacy_tokens_must_be_signed_with_the_legacy_secret() {
+        let keys = key_store(true);
+        let token = token(Algorithm::HS256, None, KEY_SECRET);
+
+        assert_eq!(
+            error_kind(keys.decode::<Value>(&token)),
+            ErrorKind::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn only_supported_algorithms_can_be_configured() {
+        assert_eq!(parse_algorithm("RS256"), Ok(Algorithm::RS256));
+        assert_eq!(parse_algorithm("EdDSA"), Ok(Algorithm::EdDSA));
+        assert!(parse_algorithm("HS256").is_err());
+    }
+}
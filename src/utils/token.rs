@@ -1,22 +1,29 @@
 use bson::oid::ObjectId;
-use jsonwebtoken::{errors::Error as JwtError, DecodingKey, EncodingKey, Header, TokenData, Validation};
-use once_cell::sync::Lazy;
+use jsonwebtoken::{errors::Error as JwtError, TokenData};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::errors::Error;
-use crate::models::user::User;
+use crate::models::user::{Role, User};
+use crate::settings::SETTINGS;
+use crate::utils::signing_keys;
 
 type TokenResult = Result<TokenData<Claims>, JwtError>;
 
-static VALIDATION: Lazy<Validation> = Lazy::new(Validation::default);
-static HEADER: Lazy<Header> = Lazy::new(Header::default);
-
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenUser {
     pub id: ObjectId,
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+impl TokenUser {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
 }
 
 impl From<User> for TokenUser {
@@ -26,37 +33,75 @@ impl From<User> for TokenUser {
             first_name: user.first_name.clone(),
             last_name: user.last_name.clone(),
             email: user.email,
+            roles: user.roles,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub exp: usize, // Expiration time (as UTC timestamp). validate_exp defaults to true in validation
     pub iat: usize, // Issued at (as UTC timestamp)
+    pub jti: String, // Unique token identifier, used to revoke the token
+    // Session the token was issued for, tokens issued before sessions
+    // existed have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<ObjectId>,
     pub user: TokenUser,
 }
 
 impl Claims {
-    pub fn new(user: User) -> Self {
+    pub fn new(user: User, session: ObjectId) -> Self {
+        let now = chrono::Local::now();
+        let ttl = chrono::Duration::seconds(SETTINGS.auth.access_token_ttl);
         Self {
-            exp: (chrono::Local::now() + chrono::Duration::days(1)).timestamp() as usize,
-            iat: chrono::Local::now().timestamp() as usize,
+            exp: (now + ttl).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            jti: Uuid::new_v4().to_string(),
+            sid: Some(session),
             user: TokenUser::from(user),
         }
     }
 }
 
-pub fn create(user: User, secret: &str) -> Result<String, Error> {
-    let encoding_key = EncodingKey::from_secret(secret.as_ref());
-    let claims = Claims::new(user);
+// Claims of the short lived token returned by signin when the user has
+// two-factor authentication enabled. It can only be exchanged for an access
+// token, its claims don't match the ones expected from access tokens.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub exp: usize,
+    pub iat: usize,
+    pub mfa_user: ObjectId,
+}
 
-    jsonwebtoken::encode(&HEADER, &claims, &encoding_key)
-        .map_err(|e| Error::TokenCreation(e.to_string()))
+impl MfaClaims {
+    pub fn new(user: ObjectId) -> Self {
+        let now = chrono::Local::now();
+        let ttl = chrono::Duration::seconds(SETTINGS.auth.mfa_token_ttl);
+        Self {
+            exp: (now + ttl).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            mfa_user: user,
+        }
+    }
 }
 
-pub fn decode(token: &str, secret: &str) -> TokenResult {
-    let decoding_key = DecodingKey::from_secret(secret.as_ref());
+pub fn create(user: User, session: ObjectId) -> Result<String, Error> {
+    let claims = Claims::new(user, session);
+
+    signing_keys::encode(&claims)
+}
+
+pub fn decode(token: &str) -> TokenResult {
+    signing_keys::decode::<Claims>(token)
+}
 
-    jsonwebtoken::decode::<Claims>(token, &decoding_key, &VALIDATION)
-}
\ No newline at end of file
+pub fn create_mfa_token(user: ObjectId) -> Result<String, Error> {
+    let claims = MfaClaims::new(user);
+
+    signing_keys::encode(&claims)
+}
+
+pub fn decode_mfa_token(token: &str) -> Result<TokenData<MfaClaims>, JwtError> {
+    signing_keys::decode::<MfaClaims>(token)
+}
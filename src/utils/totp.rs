@@ -0,0 +1,150 @@
+use rand::RngCore;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::errors::Error;
+use crate::settings::SETTINGS;
+
+const RECOVERY_CODES: usize = 10;
+
+/// Generates a base32 encoded secret, as expected by authenticator apps.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    match Secret::Raw(bytes.to_vec()).to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!(),
+    }
+}
+
+fn build(secret: &str, account_name: &str) -> Result<TOTP, Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| Error::bad_request_with_message("Invalid TOTP secret".to_string()))?;
+
+    // Clock skew is allowed by `verify`, which checks every step on its own
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        secret,
+        Some(SETTINGS.auth.totp_issuer.clone()),
+        account_name.to_string(),
+    )
+    .map_err(|e| Error::bad_request_with_message(e.to_string()))
+}
+
+/// Builds the `otpauth://` URI used to enroll the secret in an authenticator
+/// app, usually rendered as a QR code by clients.
+pub fn otpauth_uri(secret: &str, account_name: &str) -> Result<String, Error> {
+    Ok(build(secret, account_name)?.get_url())
+}
+
+/// Checks a code against the current time, allowing one step of clock skew.
+/// Returns the time step the code is valid for, callers must make sure a step
+/// isn't used twice.
+pub fn verify(secret: &str, account_name: &str, code: &str) -> Result<Option<i64>, Error> {
+    let totp = build(secret, account_name)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::bad_request_with_message(e.to_string()))?
+        .as_secs();
+
+    Ok(matching_step(&totp, code, now).map(|step| step as i64))
+}
+
+/// The step the code was generated for among the current one and its two
+/// neighbours, `now` is in seconds.
+fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<u64> {
+    let current = now / totp.step;
+
+    [current.saturating_sub(1), current, current + 1]
+        .into_iter()
+        .find(|step| totp.check(code.trim(), step * totp.step))
+}
+
+/// Generates one time recovery codes, formatted as `xxxxx-xxxxx`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Normalizes a recovery code so it can be hashed, users may type it in
+/// uppercase or without the dash.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn totp() -> TOTP {
+        let secret = Secret::Encoded(generate_secret()).to_bytes().unwrap();
+        TOTP::new(Algorithm::SHA1, 6, 0, 30, secret, None, "jane".to_string()).unwrap()
+    }
+
+    #[test]
+    fn code_of_the_current_step_matches_it() {
+        let totp = totp();
+        let code = totp.generate(NOW);
+
+        assert_eq!(matching_step(&totp, &code, NOW), Some(NOW / 30));
+    }
+
+    #[test]
+    fn one_step_of_skew_is_allowed() {
+        let totp = totp();
+        let previous = totp.generate(NOW - 30);
+        let next = totp.generate(NOW + 30);
+
+        assert_eq!(matching_step(&totp, &previous, NOW), Some(NOW / 30 - 1));
+        assert_eq!(matching_step(&totp, &next, NOW), Some(NOW / 30 + 1));
+    }
+
+    #[test]
+    fn older_codes_are_rejected() {
+        let totp = totp();
+        let code = totp.generate(NOW - 60);
+
+        assert_eq!(matching_step(&totp, &code, NOW), None);
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        let totp = totp();
+        let code = format!(" {} ", totp.generate(NOW));
+
+        assert_eq!(matching_step(&totp, &code, NOW), Some(NOW / 30));
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        assert_eq!(normalize_recovery_code(" AB12C-3D4E5 "), "ab12c3d4e5");
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_formatted() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(codes
+            .iter()
+            .all(|code| code.len() == 11 && &code[5..6] == "-"));
+
+        let mut unique = codes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), codes.len());
+    }
+}